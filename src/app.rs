@@ -1,23 +1,91 @@
-use crate::{config::Config, conflict::ConflictFinder, duplicates::FclonesRunner};
-use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+use crate::{
+    Context, Error, Result,
+    batch::{ConflictStrategy, DupeStrategy, ItemKind, RunOptions, Scan, Summary},
+    config::{Config, Profile},
+    conflict::{Conflict, ConflictFinder, OrphanAction},
+    duplicates::FclonesRunner,
+    git::{GitRepo, SNAPSHOT_MESSAGE, commit_message},
+    hooks::{Hook, HookContext, Hooks},
+    rest::{PausedFolder, RestClient},
+    scan::FolderScan,
+    selector::Selector,
+    session::Session,
+    tui::{Action, Progress},
+    versions::{find_versions, select_version},
+};
+use serde::Serialize;
 
 /// Required external dependencies for the application
-const REQUIRED_COMMANDS: &[&str] = &["fclones", "fzf", "trash", "bat"];
+const REQUIRED_COMMANDS: &[&str] = &["fclones", "trash"];
+
+/// Why items in read-only folders are left untouched
+const READ_ONLY_REASON: &str = "read-only folder";
 
-/// File types to scan for conflicts
-const CONFLICT_FILE_TYPES: &[&str] = &["md", "json"];
+/// Why a conflict whose files were all kept stays in the folder
+const IGNORED_REASON: &str = "ignored, kept all files";
 
 /// Main application struct that coordinates conflict and duplicate resolution
 pub struct App {
-    config: Config,
+    /// Folders to scan with their effective configuration
+    profiles: Vec<Profile>,
+    options: RunOptions,
+    /// Selector used to prompt the user, `None` in non-interactive mode
+    selector: Option<Box<dyn Selector>>,
+    summary: Summary,
+    session: Session,
+    /// The user quit, no further items are offered
+    quit: bool,
+    /// Offer items skipped in earlier runs again
+    include_deferred: bool,
+    /// Syncthing REST API, `None` if disabled or not reachable
+    syncthing: Option<RestClient>,
+    hooks: Hooks,
+    /// Folder being scanned
+    current: Option<Profile>,
 }
 
 impl App {
-    /// Create a new App instance, checking dependencies and loading configuration
-    pub fn new() -> Result<Self> {
+    /// Create a new App instance, checking dependencies
+    pub fn new(mut config: Config, options: RunOptions) -> Result<Self> {
         Self::check_dependencies()?;
-        let config = Config::load().context("Failed to load configuration")?;
-        Ok(Self { config })
+        let profiles = config.run_profiles(&options)?;
+        let selector = if options.non_interactive {
+            None
+        } else {
+            Some(config.selector.build()?)
+        };
+        let session = Session::load().context("Failed to load session")?;
+        let syncthing = match RestClient::from_config(&config).and_then(|client| {
+            if let Some(client) = &client {
+                client.ping()?;
+            }
+            Ok(client)
+        }) {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("warning: {:#}, continuing without the Syncthing API", e);
+                None
+            }
+        };
+        Ok(Self {
+            profiles,
+            options,
+            selector,
+            summary: Summary::default(),
+            session,
+            quit: false,
+            include_deferred: false,
+            syncthing,
+            hooks: config.hooks.clone(),
+            current: None,
+        })
+    }
+
+    /// Outcome of everything handled so far
+    pub fn summary(&self) -> &Summary {
+        &self.summary
     }
 
     /// Check if a command is installed and available in PATH
@@ -30,57 +98,590 @@ impl App {
     }
 
     /// Check that all required external dependencies are installed
+    ///
+    /// Fails with the first missing command, all of them are reported on stderr.
     fn check_dependencies() -> Result<()> {
-        let missing_commands: Vec<String> = REQUIRED_COMMANDS
+        let missing_commands: Vec<&str> = REQUIRED_COMMANDS
             .iter()
-            .filter(|&&command| !Self::check_command(command))
-            .map(|&s| s.to_string())
+            .copied()
+            .filter(|&command| !Self::check_command(command))
             .collect();
 
-        if !missing_commands.is_empty() {
-            bail!("Missing required dependencies: {:?}", missing_commands);
+        match missing_commands.first() {
+            Some(command) => Err(Error::MissingDependency {
+                command: command.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Folders the app scans
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// Run duplicate file detection and resolution in every folder
+    pub fn run_duplicate(&mut self) -> Result<()> {
+        self.run_folders(Scan::Dupes)
+    }
+
+    /// Run the given scans in every folder, one folder after the other
+    fn run_folders(&mut self, scan: Scan) -> Result<()> {
+        for index in 0..self.profiles.len() {
+            if self.quit {
+                break;
+            }
+            self.run_folder(index, scan)?;
         }
         Ok(())
     }
 
-    /// Run duplicate file detection and resolution
-    pub fn run_duplicate(&self) -> Result<()> {
-        eprintln!("searching for duplicates");
-        let mut runner = FclonesRunner::new();
-        runner.run_recursively(&self.config.working_directory.to_string_lossy())?;
-        runner.duplicate_groups.iter().for_each(|group| {
-            if let Some(choice) = group.choose() {
-                group
-                    .keep_only(choice, &self.config)
-                    .unwrap_or_else(|e| eprintln!("Error keeping file: {}", e));
+    /// Run the given scans in the folder of the profile at `index`
+    pub fn run_folder(&mut self, index: usize, scan: Scan) -> Result<()> {
+        let profile = self.profiles[index].clone();
+        self.summary.set_folder(&profile.name);
+        self.current = Some(profile.clone());
+        if let Err(e) = self
+            .hooks
+            .run(Hook::PreScan, &self.hook_context(&[], None), &())
+        {
+            eprintln!("skipping {}: {:#}", profile.name, e);
+            return Ok(());
+        }
+        let repo = self.snapshot(&profile)?;
+        let _paused = match self.pause_folder(&profile) {
+            Ok(paused) => paused,
+            Err(e) => {
+                eprintln!("skipping {}: {:#}", profile.name, e);
+                return Ok(());
             }
+        };
+        let resolved = self.summary.resolved.len();
+        let result = FolderScan::new(&profile).and_then(|files| match scan {
+            Scan::Dupes => self.duplicates_in(&profile, &files),
+            Scan::Conflicts => self.conflicts_in(&profile, &files),
+            Scan::All => self.duplicates_in(&profile, &files).and_then(|()| {
+                if self.quit {
+                    return Ok(());
+                }
+                self.conflicts_in(&profile, &files)
+            }),
         });
+        let items = &self.summary.resolved[resolved..];
+        if let Some(repo) = repo
+            && !items.is_empty()
+        {
+            let directory = &profile.config.working_directory;
+            repo.commit_all(directory, &commit_message(directory, items))?;
+            eprintln!("committed resolved state in {}", repo.root().display());
+        }
+        result
+    }
+
+    /// Commit the current state of a folder in a git repo before it is changed
+    ///
+    /// Fails if changes are staged in the repo, unless dirty repos are allowed.
+    fn snapshot(&self, profile: &Profile) -> Result<Option<GitRepo>> {
+        let directory = &profile.config.working_directory;
+        if !profile.config.git_commit || profile.read_only {
+            return Ok(None);
+        }
+        let Some(repo) = GitRepo::find(directory) else {
+            return Ok(None);
+        };
+        if !self.options.allow_dirty && repo.has_staged_changes()? {
+            return Err(Error::DirtyRepository {
+                root: repo.root().to_path_buf(),
+            });
+        }
+        if repo.commit_all(directory, SNAPSHOT_MESSAGE)? {
+            eprintln!("committed snapshot in {}", repo.root().display());
+        }
+        Ok(Some(repo))
+    }
+
+    /// Run duplicate file detection and resolution in a single folder
+    fn duplicates_in(&mut self, profile: &Profile, files: &FolderScan) -> Result<()> {
+        let config = &profile.config;
+        eprintln!(
+            "searching for duplicates in {}",
+            config.working_directory.display()
+        );
+        let runner = FclonesRunner::from_scan(files)?;
+        let total = runner.duplicate_groups.len();
+        for (index, group) in runner.duplicate_groups.iter().enumerate() {
+            let files = group.paths();
+            let key = Session::key(&files);
+            if self.is_settled(&key, ItemKind::Duplicate, &files) {
+                continue;
+            }
+            if profile.read_only {
+                self.summary
+                    .pending(ItemKind::Duplicate, files, READ_ONLY_REASON);
+                continue;
+            }
+            if let Err(e) = self.pre_resolve(&files, group) {
+                self.summary
+                    .pending(ItemKind::Duplicate, files, &format!("{:#}", e));
+                continue;
+            }
+
+            if self.options.dupe_strategy(config.dupes) == Some(DupeStrategy::Policy)
+                && let Some(keep) = group.try_auto_select()
+            {
+                match group.keep_only(keep.clone(), config) {
+                    Ok(trashed) => self.resolved(
+                        ItemKind::Duplicate,
+                        files,
+                        &format!("kept {}", keep),
+                        trashed,
+                        group,
+                    ),
+                    Err(e) => self
+                        .summary
+                        .failed(ItemKind::Duplicate, files, &e.to_string()),
+                }
+                continue;
+            }
+
+            let Some(selector) = &self.selector else {
+                self.summary
+                    .pending(ItemKind::Duplicate, files, "needs selection");
+                continue;
+            };
+            let action = group.choose(selector.as_ref(), Progress::new(index + 1, total))?;
+            let result = group.apply(&action, config);
+            if !self.record(ItemKind::Duplicate, files, key, &action, result, group) {
+                break;
+            }
+        }
         Ok(())
     }
 
-    /// Run conflict file detection and resolution
-    pub fn run_conflicts(&self) -> Result<()> {
-        eprintln!("searching for conflicts");
-        let mut finder =
-            ConflictFinder::new(self.config.working_directory.to_string_lossy().to_string());
+    /// Run conflict file detection and resolution in every folder
+    pub fn run_conflicts(&mut self) -> Result<()> {
+        self.run_folders(Scan::Conflicts)
+    }
+
+    /// Run conflict file detection and resolution in a single folder
+    fn conflicts_in(&mut self, profile: &Profile, scan: &FolderScan) -> Result<()> {
+        let config = &profile.config;
+        eprintln!(
+            "searching for conflicts in {}",
+            config.working_directory.display()
+        );
+        let mut finder = ConflictFinder::from_scan(profile, scan)?;
+        // copies may have been trashed while resolving duplicates since the scan
+        finder
+            .conflicts
+            .retain(|conflict| Path::new(&conflict.modifiedfile).exists());
 
-        for file_type in CONFLICT_FILE_TYPES {
-            finder.find_conflicts(file_type)?;
+        let total = finder.conflicts.len();
+        if total > 0 {
+            eprintln!("found {} conflicts", total);
         }
+        for (index, conflict) in finder.conflicts.iter().enumerate() {
+            let files = conflict.files();
+            let key = Session::key(&files);
+            if self.is_settled(&key, ItemKind::Conflict, &files) {
+                continue;
+            }
+            self.hooks.notify(
+                Hook::OnConflictFound,
+                &self.hook_context(&files, None),
+                conflict,
+            );
+            if profile.read_only {
+                self.summary
+                    .pending(ItemKind::Conflict, files, READ_ONLY_REASON);
+                continue;
+            }
+            if conflict.is_orphan() {
+                if !self.orphan_in(profile, scan, conflict, files, key)? {
+                    break;
+                }
+                continue;
+            }
+            if !conflict.is_valid() {
+                self.summary
+                    .pending(ItemKind::Conflict, files, "not a valid text conflict");
+                continue;
+            }
+            if let Err(e) = self.pre_resolve(&files, conflict) {
+                self.summary
+                    .pending(ItemKind::Conflict, files, &format!("{:#}", e));
+                continue;
+            }
+
+            if let Some(strategy) = self.options.conflict_strategy(config.conflicts)
+                && strategy != ConflictStrategy::Skip
+            {
+                match conflict.resolve_with(strategy, config) {
+                    Ok(Some(action)) => {
+                        self.resolved(ItemKind::Conflict, files, action, Vec::new(), conflict);
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("Error handling conflict: {}", e);
+                        self.summary
+                            .failed(ItemKind::Conflict, files, &e.to_string());
+                        continue;
+                    }
+                }
+            }
 
-        finder.print_conflicts();
-        for conflict in finder.conflicts {
-            if let Err(e) = conflict.handle_conflict(&self.config) {
-                eprintln!("Error handling conflict: {}", e);
+            let Some(selector) = &self.selector else {
+                self.summary
+                    .pending(ItemKind::Conflict, files, "needs manual merge");
+                continue;
+            };
+            let progress = Progress::new(index + 1, total);
+            let (action, result) = Self::choose(selector.as_ref(), profile, conflict, progress)?;
+            if !self.record(
+                ItemKind::Conflict,
+                files,
+                key,
+                &action,
+                without_trashed(result),
+                conflict,
+            ) {
+                break;
             }
         }
         Ok(())
     }
 
-    /// Run both duplicate and conflict resolution
-    pub fn run_all(&self) -> Result<()> {
-        self.run_duplicate()?;
-        self.run_conflicts()?;
-        Ok(())
+    /// Let the user resolve a conflict, including picking an old version of the original
+    fn choose(
+        selector: &dyn Selector,
+        profile: &Profile,
+        conflict: &Conflict,
+        progress: Progress,
+    ) -> Result<(Action, Result<Option<String>>)> {
+        loop {
+            let action = conflict.choose(selector, progress)?;
+            if action != Action::History {
+                let result = conflict.apply(&action, &profile.config);
+                return Ok((action, result));
+            }
+            let original = Path::new(&conflict.originalfile);
+            let versions = find_versions(profile, original)?;
+            if versions.is_empty() {
+                eprintln!("no old versions of {} found", original.display());
+                continue;
+            }
+            if let Some(version) = select_version(selector, &versions)? {
+                return Ok((action, conflict.restore_version(version, &profile.config)));
+            }
+        }
+    }
+
+    /// Handle a conflict copy whose original was deleted or renamed
+    ///
+    /// Returns false if the user quit.
+    fn orphan_in(
+        &mut self,
+        profile: &Profile,
+        scan: &FolderScan,
+        conflict: &Conflict,
+        files: Vec<String>,
+        key: String,
+    ) -> Result<bool> {
+        let Some(selector) = &self.selector else {
+            self.summary
+                .pending(ItemKind::Conflict, files, "original missing");
+            return Ok(true);
+        };
+        if let Err(e) = self.pre_resolve(&files, conflict) {
+            self.summary
+                .pending(ItemKind::Conflict, files, &format!("{:#}", e));
+            return Ok(true);
+        }
+        let renamed = conflict.find_renamed_original(scan);
+        let choice = conflict.choose_orphan(selector.as_ref(), renamed.as_ref())?;
+        let action = match choice {
+            OrphanAction::Relink => {
+                let (original, _) = renamed.expect("offered only with a renamed original");
+                let relinked = Conflict::new(original, conflict.modifiedfile.clone())
+                    .with_sizes(None, conflict.modified_size);
+                let progress = Progress::new(1, 1);
+                let (action, result) =
+                    Self::choose(selector.as_ref(), profile, &relinked, progress)?;
+                let files = relinked.files();
+                let key = Session::key(&files);
+                return Ok(self.record(
+                    ItemKind::Conflict,
+                    files,
+                    key,
+                    &action,
+                    without_trashed(result),
+                    &relinked,
+                ));
+            }
+            OrphanAction::Restore => Action::Keep(1),
+            OrphanAction::Trash => Action::Keep(0),
+            OrphanAction::Skip => Action::Skip,
+            OrphanAction::Quit => Action::Quit,
+        };
+        let result = conflict.apply_orphan(choice, &profile.config);
+        Ok(self.record(
+            ItemKind::Conflict,
+            files,
+            key,
+            &action,
+            without_trashed(result),
+            conflict,
+        ))
+    }
+
+    /// Pause a folder in Syncthing while it is changed, it is resumed when the guard drops
+    ///
+    /// Fails if Syncthing is busy with the folder. If Syncthing stopped responding, the
+    /// run continues without it.
+    fn pause_folder(&mut self, profile: &Profile) -> Result<Option<PausedFolder>> {
+        let Some(client) = &self.syncthing else {
+            return Ok(None);
+        };
+        if profile.read_only {
+            return Ok(None);
+        }
+        match client.pause_folder(&profile.config.working_directory) {
+            Ok(paused) => Ok(paused),
+            Err(e) if client.ping().is_err() => {
+                eprintln!("warning: {:#}, continuing without the Syncthing API", e);
+                self.syncthing = None;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check whether an item was settled in an earlier session and must not be offered
+    fn is_settled(&mut self, key: &str, kind: ItemKind, files: &[String]) -> bool {
+        if self.session.is_ignored(key) {
+            return true;
+        }
+        if !self.include_deferred && self.session.is_deferred(key) {
+            self.summary
+                .pending(kind, files.to_vec(), "deferred in an earlier run");
+            return true;
+        }
+        false
+    }
+
+    /// Context passed to hooks about files in the current folder
+    fn hook_context<'a>(&'a self, files: &'a [String], action: Option<&'a str>) -> HookContext<'a> {
+        HookContext {
+            folder: self.current.as_ref().map(|profile| profile.name.as_str()),
+            directory: self
+                .current
+                .as_ref()
+                .map(|profile| profile.config.working_directory.as_path()),
+            files,
+            action,
+        }
+    }
+
+    /// Run the pre_resolve hook, an error means the item must be left alone
+    fn pre_resolve(&self, files: &[String], item: &impl Serialize) -> Result<()> {
+        self.hooks
+            .run(Hook::PreResolve, &self.hook_context(files, None), item)
+    }
+
+    /// Record a resolved item in the summary and the session
+    ///
+    /// `trashed` are the files of a duplicate group moved to the trash.
+    fn resolved(
+        &mut self,
+        kind: ItemKind,
+        files: Vec<String>,
+        action: &str,
+        trashed: Vec<String>,
+        item: &impl Serialize,
+    ) {
+        self.hooks.notify(
+            Hook::PostResolve,
+            &self.hook_context(&files, Some(action)),
+            item,
+        );
+        for file in &trashed {
+            let trashed = [file.clone()];
+            self.hooks.notify(
+                Hook::OnDuplicateTrashed,
+                &self.hook_context(&trashed, Some(action)),
+                item,
+            );
+        }
+        self.session.decide(kind, files.clone(), action);
+        self.summary.resolved(kind, files, action, trashed);
+    }
+
+    /// Record the outcome of an item in the summary and session
+    ///
+    /// `result` is what was done and the files of a duplicate group moved to the trash.
+    /// Returns false if the user quit.
+    fn record(
+        &mut self,
+        kind: ItemKind,
+        files: Vec<String>,
+        key: String,
+        action: &Action,
+        result: Result<Option<(String, Vec<String>)>>,
+        item: &impl Serialize,
+    ) -> bool {
+        match (action, result) {
+            (Action::Quit, _) => {
+                self.quit = true;
+                return false;
+            }
+            (_, Err(e)) => {
+                eprintln!("Error applying {:?}: {}", action, e);
+                self.summary.failed(kind, files, &e.to_string());
+            }
+            (_, Ok(Some((done, trashed)))) => {
+                if *action == Action::KeepAll {
+                    self.session.ignored.insert(key);
+                }
+                self.resolved(kind, files, &done, trashed, item);
+            }
+            (Action::KeepAll, Ok(None)) => {
+                self.session.ignored.insert(key);
+                self.summary.pending(kind, files, IGNORED_REASON);
+            }
+            (_, Ok(None)) => {
+                self.session.deferred.insert(key);
+                self.summary.pending(kind, files, "skipped");
+            }
+        }
+        true
+    }
+
+    /// Run the given scans and remember them for `resume`
+    ///
+    /// Non-interactive runs keep the resume state of the last interactive session.
+    pub fn run(&mut self, scan: Scan) -> Result<()> {
+        if !self.options.non_interactive {
+            self.session.command = Some(scan);
+        }
+        match scan {
+            Scan::Dupes => self.run_duplicate(),
+            Scan::Conflicts => self.run_conflicts(),
+            Scan::All => self.run_all(),
+        }
+    }
+
+    /// Repeat the last run, offering the items skipped earlier again
+    pub fn resume(&mut self) -> Result<()> {
+        let scan = self.session.command.unwrap_or(Scan::All);
+        if !self.session.interrupted && self.session.deferred.is_empty() {
+            eprintln!("nothing to resume, running {:?} scan", scan);
+        }
+        self.include_deferred = true;
+        self.run(scan)
+    }
+
+    /// Queue the pending items for the next interactive session and start a new summary
+    ///
+    /// Returns the summary of everything handled since the last call and the number of
+    /// items queued that were not queued before.
+    pub fn defer_pending(&mut self) -> Result<(Summary, usize)> {
+        let mut queued = 0;
+        for item in &self.summary.pending {
+            if item.action == READ_ONLY_REASON || item.action == IGNORED_REASON {
+                continue;
+            }
+            if self.session.deferred.insert(Session::key(&item.files)) {
+                queued += 1;
+            }
+        }
+        self.session.save().context("Failed to save session")?;
+        self.hooks
+            .notify(Hook::PostRun, &HookContext::default(), &self.summary);
+        Ok((std::mem::take(&mut self.summary), queued))
+    }
+
+    /// Save the session
+    ///
+    /// If the user quit, the session is marked as interrupted so `resume` continues it.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.profiles.len() > 1 {
+            self.summary.print_per_folder();
+        }
+        self.hooks
+            .notify(Hook::PostRun, &HookContext::default(), &self.summary);
+        if !self.options.non_interactive {
+            self.session.interrupted = self.quit;
+        }
+        self.session.prune();
+        if !self.session.deferred.is_empty() && !self.options.non_interactive {
+            eprintln!(
+                "{} skipped items pending, run `resolvething resume` to go through them",
+                self.session.deferred.len()
+            );
+        }
+        self.session.save().context("Failed to save session")
+    }
+
+    /// Run both duplicate and conflict resolution, walking each folder once
+    pub fn run_all(&mut self) -> Result<()> {
+        self.run_folders(Scan::All)
+    }
+}
+
+/// Result of resolving a conflict as recorded, resolving conflicts trashes no duplicates
+fn without_trashed(result: Result<Option<String>>) -> Result<Option<(String, Vec<String>)>> {
+    result.map(|done| done.map(|done| (done, Vec::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Non-interactive app without the Syncthing API or a saved session
+    fn app(profiles: Vec<Profile>, hooks: Hooks) -> App {
+        App {
+            profiles,
+            options: RunOptions {
+                non_interactive: true,
+                ..Default::default()
+            },
+            selector: None,
+            summary: Summary::default(),
+            session: Session::default(),
+            quit: false,
+            include_deferred: false,
+            syncthing: None,
+            hooks,
+            current: None,
+        }
+    }
+
+    #[test]
+    fn test_run_all_scans_each_folder_once() {
+        let dir = TempDir::new("run-all");
+        let log = dir.join("pre_scan.log");
+        for name in ["a", "b"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            std::fs::write(dir.join(name).join("notes.md"), name).unwrap();
+        }
+        let profiles = ["a", "b"]
+            .map(|name| Profile::for_test(&dir.join(name)))
+            .to_vec();
+        let hooks = Hooks {
+            pre_scan: Some(format!(
+                "echo \"$RESOLVETHING_FOLDER\" >> {}",
+                log.display()
+            )),
+            ..Default::default()
+        };
+
+        app(profiles, hooks).run_all().unwrap();
+        let scanned = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(
+            scanned.lines().collect::<Vec<_>>(),
+            [dir.join("a"), dir.join("b")].map(|path| path.display().to_string())
+        );
     }
 }
@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::config::Profile;
+use crate::list::{Entry, OutputFormat, format_age, write_entries};
+use crate::scan::FolderScan;
+use crate::selector::Selector;
+use crate::trash::Trash;
+use crate::tui::format_size;
+use crate::{Error, Result, parse_scaled};
+
+/// Kind of leftover file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactKind {
+    /// Partial download of Syncthing (`.syncthing.*.tmp` or `~syncthing~*.tmp`)
+    SyncthingTemp,
+    /// Original file backup (`*.orig`)
+    Orig,
+    /// Other temporary file (`*.tmp`)
+    Tmp,
+    /// Swap file of vim (`.*.swp`, `.*.swo`, ...)
+    VimSwap,
+    /// Backup or autosave of an editor (`*~`, `*.bak` or `#*#`)
+    Backup,
+}
+
+impl ArtifactKind {
+    /// Short human readable name of the kind
+    pub fn label(&self) -> &'static str {
+        match self {
+            ArtifactKind::SyncthingTemp => "syncthing-temp",
+            ArtifactKind::Orig => "orig",
+            ArtifactKind::Tmp => "tmp",
+            ArtifactKind::VimSwap => "vim-swap",
+            ArtifactKind::Backup => "backup",
+        }
+    }
+
+    /// Classify a file by its name
+    pub fn of(name: &str) -> Option<Self> {
+        if (name.starts_with(".syncthing.") || name.starts_with("~syncthing~"))
+            && name.ends_with(".tmp")
+        {
+            Some(Self::SyncthingTemp)
+        } else if name.ends_with(".orig") {
+            Some(Self::Orig)
+        } else if name.ends_with(".tmp") {
+            Some(Self::Tmp)
+        } else if is_vim_swap(name) {
+            Some(Self::VimSwap)
+        } else if name.ends_with('~')
+            || name.ends_with(".bak")
+            || (name.len() > 2 && name.starts_with('#') && name.ends_with('#'))
+        {
+            Some(Self::Backup)
+        } else {
+            None
+        }
+    }
+}
+
+/// Vim names swap files `.name.swp`, falling back to `.swo` down to `.swa`
+fn is_vim_swap(name: &str) -> bool {
+    let Some(rest) = name.strip_prefix('.') else {
+        return false;
+    };
+    rest.len() > 4
+        && rest
+            .rsplit_once(".sw")
+            .is_some_and(|(_, last)| matches!(last.as_bytes(), [b'a'..=b'p']))
+}
+
+/// A leftover file of Syncthing or another tool
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    pub path: String,
+    pub kind: ArtifactKind,
+    pub size: u64,
+    /// Seconds since the file was last modified
+    pub age: Option<u64>,
+}
+
+/// Find all artifacts in a folder, leaving out old versions and ignored files
+pub fn find_artifacts(profile: &Profile) -> Result<Vec<Artifact>> {
+    artifacts_in(&FolderScan::new(profile)?)
+}
+
+/// The artifacts among the files of a scan
+pub fn artifacts_in(scan: &FolderScan) -> Result<Vec<Artifact>> {
+    let now = SystemTime::now();
+    scan.artifacts()
+        .map(|(file, kind)| {
+            Ok(Artifact {
+                path: path_string(&file.path)?,
+                kind,
+                size: file.size,
+                age: file
+                    .modified
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .map(|age| age.as_secs()),
+            })
+        })
+        .collect()
+}
+
+/// Artifacts of a folder not modified for `min_age` seconds and not open in any process
+pub fn stale_artifacts(
+    profile: &Profile,
+    min_age: u64,
+    open: &HashSet<PathBuf>,
+) -> Result<Vec<Artifact>> {
+    Ok(find_artifacts(profile)?
+        .into_iter()
+        .filter(|artifact| artifact.age.is_some_and(|age| age >= min_age))
+        .filter(|artifact| {
+            let path = Path::new(&artifact.path);
+            !open.contains(&path.canonicalize().unwrap_or_else(|_| path.to_path_buf()))
+        })
+        .collect())
+}
+
+/// Files currently open in any process, from `/proc/*/fd`
+///
+/// Empty where `/proc` is not available, processes of other users are not visible.
+pub fn open_files() -> HashSet<PathBuf> {
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return HashSet::new();
+    };
+    processes
+        .filter_map(|e| e.ok())
+        .filter_map(|process| std::fs::read_dir(process.path().join("fd")).ok())
+        .flatten()
+        .filter_map(|fd| std::fs::read_link(fd.ok()?.path()).ok())
+        .collect()
+}
+
+/// Trash stale artifacts in all folders after listing them
+///
+/// Without `yes`, the selector has to confirm; without a selector, the artifacts are only listed.
+pub fn clean_artifacts(
+    profiles: &[Profile],
+    older_than: Option<u64>,
+    selector: Option<&dyn Selector>,
+    yes: bool,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<()> {
+    let open = open_files();
+    let mut found = Vec::new();
+    for profile in profiles {
+        let min_age = match older_than {
+            Some(age) => age,
+            None => parse_age(&profile.config.artifact_age)?,
+        };
+        for artifact in stale_artifacts(profile, min_age, &open)? {
+            found.push((profile, artifact));
+        }
+    }
+    let entries = found
+        .iter()
+        .map(|(profile, item)| Entry {
+            folder: &profile.name,
+            item,
+        })
+        .collect();
+    write_entries(
+        out,
+        format,
+        entries,
+        &["FOLDER", "KIND", "SIZE", "AGE", "PATH"],
+        |entry| {
+            vec![
+                entry.folder.to_string(),
+                entry.item.kind.label().to_string(),
+                format_size(entry.item.size),
+                entry.item.age.map_or_else(String::new, format_age),
+                entry.item.path.clone(),
+            ]
+        },
+    )?;
+    out.flush()?;
+
+    let (read_only, writable): (Vec<_>, Vec<_>) =
+        found.iter().partition(|(profile, _)| profile.read_only);
+    if !read_only.is_empty() {
+        eprintln!("leaving {} artifacts in read-only folders", read_only.len());
+    }
+    if writable.is_empty() {
+        eprintln!("no stale artifacts to trash");
+        return Ok(());
+    }
+    let size: u64 = writable.iter().map(|(_, artifact)| artifact.size).sum();
+    let prompt = format!("trash {} artifacts, {}?", writable.len(), format_size(size));
+    let confirmed = yes
+        || match selector {
+            Some(selector) => {
+                selector.select(&prompt, &["yes".to_string(), "no".to_string()])? == Some(0)
+            }
+            None => {
+                eprintln!("pass --yes to trash them");
+                false
+            }
+        };
+    if !confirmed {
+        return Ok(());
+    }
+    let mut failed = 0;
+    for (profile, artifact) in writable {
+        if let Err(e) = Trash::trash(&artifact.path, &profile.config) {
+            eprintln!("Error: {:#}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(Error::TrashFailed { failed });
+    }
+    Ok(())
+}
+
+/// Parse an age like `30m`, `12h` or `7d`, plain numbers are seconds
+pub fn parse_age(age: &str) -> Result<u64> {
+    let factor = |unit: &str| match unit {
+        "" | "s" => Some(1),
+        "m" => Some(60),
+        "h" => Some(3_600),
+        "d" => Some(86_400),
+        _ => None,
+    };
+    match parse_scaled(age, factor) {
+        Some(seconds) => Ok(seconds),
+        None => Err(Error::InvalidConfigValue {
+            key: "age".to_string(),
+            value: age.trim().to_string(),
+            expected: "use e.g. 30m, 12h or 7d".to_string(),
+        }),
+    }
+}
+
+fn path_string(path: &Path) -> Result<String> {
+    path.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| Error::InvalidPath {
+            path: path.to_path_buf(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_kind() {
+        assert_eq!(
+            ArtifactKind::of(".syncthing.notes.md.tmp"),
+            Some(ArtifactKind::SyncthingTemp)
+        );
+        assert_eq!(
+            ArtifactKind::of("~syncthing~notes.md.tmp"),
+            Some(ArtifactKind::SyncthingTemp)
+        );
+        assert_eq!(ArtifactKind::of("notes.md.orig"), Some(ArtifactKind::Orig));
+        assert_eq!(ArtifactKind::of("notes.tmp"), Some(ArtifactKind::Tmp));
+        assert_eq!(
+            ArtifactKind::of(".notes.md.swp"),
+            Some(ArtifactKind::VimSwap)
+        );
+        assert_eq!(
+            ArtifactKind::of(".notes.md.swo"),
+            Some(ArtifactKind::VimSwap)
+        );
+        assert_eq!(ArtifactKind::of("notes.md~"), Some(ArtifactKind::Backup));
+        assert_eq!(ArtifactKind::of("#notes.md#"), Some(ArtifactKind::Backup));
+        assert_eq!(ArtifactKind::of("notes.bak"), Some(ArtifactKind::Backup));
+        assert_eq!(ArtifactKind::of("notes.md"), None);
+        assert_eq!(ArtifactKind::of(".swp"), None);
+        assert_eq!(ArtifactKind::of("notes.swp"), None);
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90").unwrap(), 90);
+        assert_eq!(parse_age("30m").unwrap(), 1_800);
+        assert_eq!(parse_age("2d").unwrap(), 172_800);
+        assert!(parse_age("d").is_err());
+        assert!(matches!(
+            parse_age("3w"),
+            Err(Error::InvalidConfigValue { value, .. }) if value == "3w"
+        ));
+        assert!(parse_age("213503982334602d").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// How conflicts are resolved without asking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Resolve only conflicts that merge without losing content
+    AutoMerge,
+    /// Keep whichever version was modified last
+    KeepNewest,
+    /// Keep the original file, trash the conflict copy
+    KeepOriginal,
+    /// Leave all conflicts untouched
+    Skip,
+}
+
+/// How duplicate groups are resolved without asking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DupeStrategy {
+    /// Resolve groups where the file types decide which file to keep
+    Policy,
+    /// Leave all duplicate groups untouched
+    Skip,
+}
+
+/// Scans a run performs, remembered so `resume` can repeat them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scan {
+    Dupes,
+    Conflicts,
+    All,
+}
+
+/// Kind of item handled during a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Conflict,
+    Duplicate,
+}
+
+/// A conflict or duplicate group and what happened to it
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryItem {
+    /// Profile name of the folder the item was found in
+    pub folder: String,
+    pub kind: ItemKind,
+    pub files: Vec<String>,
+    /// What was done, or why the item is still pending
+    pub action: String,
+    /// Files of a duplicate group moved to the trash
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trashed: Vec<String>,
+}
+
+/// Outcome of a run, printed as JSON in non-interactive mode
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub resolved: Vec<SummaryItem>,
+    pub pending: Vec<SummaryItem>,
+    pub failed: Vec<SummaryItem>,
+    /// Folders in the order they were scanned
+    #[serde(skip)]
+    folders: Vec<String>,
+}
+
+impl Summary {
+    /// Attribute all following items to the given folder
+    pub fn set_folder(&mut self, folder: &str) {
+        if !self.folders.iter().any(|f| f == folder) {
+            self.folders.push(folder.to_string());
+        }
+    }
+
+    fn item(&self, kind: ItemKind, files: Vec<String>, action: &str) -> SummaryItem {
+        SummaryItem {
+            folder: self.folders.last().cloned().unwrap_or_default(),
+            kind,
+            files,
+            action: action.to_string(),
+            trashed: Vec::new(),
+        }
+    }
+
+    pub fn resolved(
+        &mut self,
+        kind: ItemKind,
+        files: Vec<String>,
+        action: &str,
+        trashed: Vec<String>,
+    ) {
+        let item = SummaryItem {
+            trashed,
+            ..self.item(kind, files, action)
+        };
+        self.resolved.push(item);
+    }
+
+    pub fn pending(&mut self, kind: ItemKind, files: Vec<String>, reason: &str) {
+        let item = self.item(kind, files, reason);
+        self.pending.push(item);
+    }
+
+    pub fn failed(&mut self, kind: ItemKind, files: Vec<String>, error: &str) {
+        let item = self.item(kind, files, error);
+        self.failed.push(item);
+    }
+
+    /// Print the number of resolved, pending and failed items of each folder
+    pub fn print_per_folder(&self) {
+        let count = |items: &[SummaryItem], folder: &str| {
+            items.iter().filter(|item| item.folder == folder).count()
+        };
+        for folder in &self.folders {
+            eprintln!(
+                "{}: {} resolved, {} pending, {} failed",
+                folder,
+                count(&self.resolved, folder),
+                count(&self.pending, folder),
+                count(&self.failed, folder)
+            );
+        }
+    }
+
+    /// Serialize the summary as a single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Summary is always serializable")
+    }
+}
+
+/// Options controlling which folders a run scans and how it resolves items
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Only scan the folder with this profile name
+    pub profile: Option<String>,
+    /// Only scan this folder
+    pub folder: Option<PathBuf>,
+    /// Scan the folders shared by the local Syncthing instance
+    pub discover: bool,
+    /// Never prompt, leave everything the strategies do not resolve untouched
+    pub non_interactive: bool,
+    /// Strategy applied to conflicts before prompting
+    pub conflicts: Option<ConflictStrategy>,
+    /// Strategy applied to duplicate groups before prompting
+    pub dupes: Option<DupeStrategy>,
+    /// Change folders in git repositories even if changes are staged
+    pub allow_dirty: bool,
+}
+
+impl RunOptions {
+    /// Strategy for conflicts, falling back to the configured one
+    /// and to safe merges in non-interactive mode
+    pub fn conflict_strategy(
+        &self,
+        configured: Option<ConflictStrategy>,
+    ) -> Option<ConflictStrategy> {
+        match self.conflicts.or(configured) {
+            None if self.non_interactive => Some(ConflictStrategy::AutoMerge),
+            strategy => strategy,
+        }
+    }
+
+    /// Strategy for duplicates, falling back to the configured one
+    /// and to the file type policy in non-interactive mode
+    pub fn dupe_strategy(&self, configured: Option<DupeStrategy>) -> Option<DupeStrategy> {
+        match self.dupes.or(configured) {
+            None if self.non_interactive => Some(DupeStrategy::Policy),
+            strategy => strategy,
+        }
+    }
+}
+
+/// Merge two versions of a text if one contains all lines of the other in order
+///
+/// Returns the merged text, or `None` if both versions have changes of their own.
+pub fn merge_superset<'a>(a: &'a str, b: &'a str) -> Option<&'a str> {
+    if a == b || contains_lines(a, b) {
+        Some(a)
+    } else if contains_lines(b, a) {
+        Some(b)
+    } else {
+        None
+    }
+}
+
+/// Check whether the lines of `subset` appear in `superset` in the same order
+fn contains_lines(superset: &str, subset: &str) -> bool {
+    let mut lines = superset.lines();
+    subset
+        .lines()
+        .all(|wanted| lines.by_ref().any(|line| line == wanted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_superset() {
+        assert_eq!(merge_superset("a\nb\n", "a\nb\n"), Some("a\nb\n"));
+        assert_eq!(merge_superset("a\nb\nc\n", "a\nc\n"), Some("a\nb\nc\n"));
+        assert_eq!(merge_superset("a\n", "x\na\ny\n"), Some("x\na\ny\n"));
+        assert_eq!(merge_superset("a\nb\n", "a\nc\n"), None);
+        assert_eq!(merge_superset("a\nb\n", "b\na\n"), None);
+    }
+
+    #[test]
+    fn test_default_strategies() {
+        let interactive = RunOptions::default();
+        assert_eq!(interactive.conflict_strategy(None), None);
+        assert_eq!(interactive.dupe_strategy(None), None);
+        assert_eq!(
+            interactive.dupe_strategy(Some(DupeStrategy::Skip)),
+            Some(DupeStrategy::Skip)
+        );
+
+        let batch = RunOptions {
+            non_interactive: true,
+            conflicts: Some(ConflictStrategy::KeepOriginal),
+            ..Default::default()
+        };
+        assert_eq!(
+            batch.conflict_strategy(Some(ConflictStrategy::KeepNewest)),
+            Some(ConflictStrategy::KeepOriginal)
+        );
+        assert_eq!(batch.dupe_strategy(None), Some(DupeStrategy::Policy));
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let mut summary = Summary::default();
+        summary.set_folder("wiki");
+        summary.resolved(
+            ItemKind::Conflict,
+            vec!["a.md".to_string()],
+            "kept original",
+            Vec::new(),
+        );
+        summary.resolved(
+            ItemKind::Duplicate,
+            vec!["d".to_string(), "e".to_string()],
+            "kept d",
+            vec!["e".to_string()],
+        );
+        summary.pending(
+            ItemKind::Duplicate,
+            vec!["b".to_string(), "c".to_string()],
+            "ambiguous",
+        );
+        assert_eq!(
+            summary.to_json(),
+            r#"{"resolved":[{"folder":"wiki","kind":"conflict","files":["a.md"],"action":"kept original"},{"folder":"wiki","kind":"duplicate","files":["d","e"],"action":"kept d","trashed":["e"]}],"pending":[{"folder":"wiki","kind":"duplicate","files":["b","c"],"action":"ambiguous"}],"failed":[]}"#
+        );
+    }
+}
@@ -1,9 +1,98 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueHint};
+use clap_complete::engine::ValueCompleter;
+use clap_complete::{
+    ArgValueCandidates, ArgValueCompleter, CompletionCandidate, PathCompleter, Shell,
+};
+
+use crate::artifacts::parse_age;
+use crate::batch::{ConflictStrategy, DupeStrategy, RunOptions};
+use crate::config::Config;
+use crate::list::{ListKind, OutputFormat};
+use crate::prune::PrunePolicy;
+use crate::status::{Thresholds, parse_size};
 
 #[derive(Parser)]
+#[command(version, about)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// Use this config file instead of the user config
+    #[arg(long, global = true, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub config: Option<PathBuf>,
+
+    /// Never prompt, leave anything the strategies cannot resolve untouched
+    /// and print a JSON summary
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Only scan the configured folder with this profile name
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        conflicts_with = "folder",
+        add = ArgValueCandidates::new(profile_candidates)
+    )]
+    pub profile: Option<String>,
+
+    /// Only scan this folder
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        value_hint = ValueHint::DirPath,
+        add = ArgValueCompleter::new(complete_folder)
+    )]
+    pub folder: Option<PathBuf>,
+
+    /// Scan every folder found in Syncthing's config.xml
+    #[arg(long, global = true)]
+    pub discover: bool,
+
+    /// Strategy to resolve conflicts without asking
+    #[arg(long, global = true, value_name = "STRATEGY")]
+    pub conflicts: Option<ConflictStrategy>,
+
+    /// Strategy to resolve duplicates without asking
+    #[arg(long, global = true, value_name = "STRATEGY")]
+    pub dupes: Option<DupeStrategy>,
+
+    /// Change folders in git repositories even if changes are staged there
+    #[arg(long, global = true)]
+    pub allow_dirty: bool,
+}
+
+impl Cli {
+    /// Options for the run selected by the flags
+    pub fn run_options(&self) -> RunOptions {
+        RunOptions {
+            profile: self.profile.clone(),
+            folder: self.folder.clone(),
+            discover: self.discover,
+            non_interactive: self.non_interactive,
+            conflicts: self.conflicts,
+            dupes: self.dupes,
+            allow_dirty: self.allow_dirty,
+        }
+    }
+
+    /// Config values set by the flags, taking precedence over all config sources
+    pub fn config_overrides(&self) -> toml::Table {
+        let mut table = toml::Table::new();
+        if self.discover {
+            table.insert("discover".to_string(), true.into());
+        }
+        if let Some(conflicts) = self.conflicts.and_then(|c| toml::Value::try_from(c).ok()) {
+            table.insert("conflicts".to_string(), conflicts);
+        }
+        if let Some(dupes) = self.dupes.and_then(|d| toml::Value::try_from(d).ok()) {
+            table.insert("dupes".to_string(), dupes);
+        }
+        table
+    }
 }
 
 #[derive(Subcommand)]
@@ -14,4 +103,195 @@ pub enum Commands {
     Conflicts,
     /// Run all commands
     All,
+    /// Repeat the last run, offering items skipped earlier again
+    Resume,
+    /// Watch the folders and resolve new conflicts and artifacts as they appear
+    ///
+    /// Runs without prompting. Whatever the strategies do not resolve is queued
+    /// for the next `resolvething resume`.
+    Watch {
+        /// Seconds the folders must be quiet before a burst of changes is handled
+        #[arg(long, value_name = "SECONDS", default_value_t = 2)]
+        debounce: u64,
+    },
+    /// Manage files that are never offered again
+    Ignore {
+        #[command(subcommand)]
+        command: Option<IgnoreCommand>,
+    },
+    /// Print conflicts, duplicate groups or artifacts without prompting
+    List {
+        kind: ListKind,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Count pending items per folder, exit with 0 if clean, 1 if items are pending, 2 on errors
+    Status {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        /// Do not scan for duplicates, which is the slowest part
+        #[arg(long)]
+        no_dupes: bool,
+        /// Only report conflicts as pending if there are more than N
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        warn_conflicts: usize,
+        /// Only report duplicate groups as pending if there are more than N
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        warn_dupes: usize,
+        /// Only report artifacts as pending if there are more than N
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        warn_artifacts: usize,
+        /// Report folders whose old versions use more than SIZE, e.g. 2G
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        warn_versions: Option<u64>,
+    },
+    /// List, preview, diff and restore old versions of a file kept by Syncthing
+    Versions {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+        /// Only print the versions, like in non-interactive mode
+        #[arg(long)]
+        list: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// List stale temporary, partial and backup files no process has open and trash them
+    Artifacts {
+        /// Only consider files not modified for AGE, e.g. 12h or 7d, instead of `artifact_age`
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Option<u64>,
+        /// Trash without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Remove old versions kept by Syncthing, all versions are kept unless a policy is given
+    PruneVersions {
+        /// Keep the newest N versions of every file
+        #[arg(long, value_name = "N")]
+        keep_last: Option<usize>,
+        /// Keep versions archived less than DAYS days ago
+        #[arg(long, value_name = "DAYS")]
+        keep_days: Option<u64>,
+        /// Remove the oldest versions of each folder until they use at most SIZE, e.g. 2G
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// Remove versions identical to the current file or to the next newer version
+        #[arg(long)]
+        drop_identical: bool,
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Print a completion script for a shell
+    ///
+    /// For completion of profile names and configured folders, use dynamic completion
+    /// instead, e.g. `source <(COMPLETE=bash resolvething)` in bash or
+    /// `COMPLETE=fish resolvething | source` in fish.
+    Completions { shell: Shell },
+    /// Write man pages for resolvething and all subcommands
+    Man {
+        /// Directory to write the pages to
+        #[arg(long, value_name = "DIR", default_value = ".", value_hint = ValueHint::DirPath)]
+        out_dir: PathBuf,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+/// Configured folders for dynamic completion, nothing if the config cannot be loaded
+fn configured_profiles() -> Vec<crate::config::Profile> {
+    Config::load(None, toml::Table::new())
+        .map(|config| config.profiles())
+        .unwrap_or_default()
+}
+
+fn profile_candidates() -> Vec<CompletionCandidate> {
+    configured_profiles()
+        .into_iter()
+        .map(|profile| {
+            let path = profile.config.working_directory.display().to_string();
+            CompletionCandidate::new(profile.name).help(Some(path.into()))
+        })
+        .collect()
+}
+
+fn complete_folder(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
+    let prefix = current.to_string_lossy();
+    let mut candidates: Vec<CompletionCandidate> = configured_profiles()
+        .into_iter()
+        .map(|profile| profile.config.working_directory.display().to_string())
+        .filter(|path| path.starts_with(&*prefix))
+        .map(CompletionCandidate::new)
+        .collect();
+    candidates.extend(PathCompleter::dir().complete(current));
+    candidates
+}
+
+impl Commands {
+    /// Thresholds given to the `status` command
+    pub fn thresholds(&self) -> Thresholds {
+        match self {
+            Commands::Status {
+                warn_conflicts,
+                warn_dupes,
+                warn_artifacts,
+                warn_versions,
+                ..
+            } => Thresholds {
+                conflicts: *warn_conflicts,
+                dupes: *warn_dupes,
+                artifacts: *warn_artifacts,
+                versions: *warn_versions,
+            },
+            _ => Thresholds::default(),
+        }
+    }
+
+    /// Policy given to the `prune-versions` command
+    pub fn prune_policy(&self) -> PrunePolicy {
+        match self {
+            Commands::PruneVersions {
+                keep_last,
+                keep_days,
+                max_size,
+                drop_identical,
+                ..
+            } => PrunePolicy {
+                keep_last: *keep_last,
+                keep_days: *keep_days,
+                max_size: *max_size,
+                drop_identical: *drop_identical,
+            },
+            _ => PrunePolicy::default(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Create a config file by answering a few questions
+    Init,
+    /// Print the effective configuration and where each value comes from
+    Show,
+    /// Check that configured folders exist and configured commands are installed
+    Check,
+}
+
+#[derive(Subcommand)]
+pub enum IgnoreCommand {
+    /// List ignored items and patterns
+    List,
+    /// Never offer groups whose files all match a glob pattern, e.g. "**/template.md"
+    Add { pattern: String },
+    /// Remove an ignore pattern
+    Remove { pattern: String },
+    /// Forget all ignored items and patterns
+    Clear,
 }
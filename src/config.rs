@@ -1,23 +1,234 @@
-use anyhow::{Context, Result};
+use crate::artifacts::parse_age;
+use crate::batch::{ConflictStrategy, DupeStrategy, RunOptions};
+use crate::diff::{MergeTool, split_command};
+use crate::hooks::Hooks;
+use crate::selector::SelectorKind;
+use crate::syncthing::{self, STVERSIONS_DIR, SyncthingFolder};
+use crate::{Context, Error, Result, bail};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Name of the project config file, found by walking up from the current directory
+pub const PROJECT_CONFIG_NAME: &str = ".resolvething.toml";
+
+/// Prefix of environment variables overriding config values
+const ENV_PREFIX: &str = "RESOLVETHING_";
+
+/// Version of the config file format written by this release
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Settings in the order they are saved, with the comment written above each
+const KEY_COMMENTS: &[(&str, &str)] = &[
+    ("version", "Version of the config format, do not change"),
+    (
+        "working_directory",
+        "Directory to search when no folders are configured",
+    ),
+    ("trash_command", "Command moving files to the trash"),
+    (
+        "selector",
+        "How to pick files: auto, tui, fzf, skim, rofi, dmenu or prompt",
+    ),
+    (
+        "merge_tool",
+        "Merge tool preset: nvim, vimdiff, meld, kdiff3, emacs or code",
+    ),
+    (
+        "merge_command",
+        "Custom merge command using {local}, {remote}, {base} and {merged}",
+    ),
+    (
+        "merge_trust_exit_code",
+        "Treat a successful exit of the merge tool as resolved",
+    ),
+    (
+        "conflict_file_types",
+        "File extensions scanned for conflicts",
+    ),
+    (
+        "conflicts",
+        "Resolve conflicts without asking: auto-merge, keep-newest, keep-original or skip",
+    ),
+    ("dupes", "Resolve duplicates without asking: policy or skip"),
+    (
+        "artifact_age",
+        "Age after which leftover temporary and backup files are stale, e.g. 12h or 7d",
+    ),
+    (
+        "git_commit",
+        "Commit tracked files before and after changing folders inside git repositories",
+    ),
+    ("discover", "Scan every folder shared by Syncthing"),
+    (
+        "syncthing_config",
+        "Location of Syncthing's config.xml, found automatically if unset",
+    ),
+    (
+        "syncthing_api",
+        "Pause folders through Syncthing's REST API while changing them",
+    ),
+    (
+        "syncthing_address",
+        "Address of Syncthing's REST API, read from config.xml if unset",
+    ),
+    (
+        "syncthing_api_key",
+        "API key of Syncthing's REST API, read from config.xml if unset",
+    ),
+];
+
+/// Migrations of config files, the first one migrates version 1 to 2
+const MIGRATIONS: &[fn(&mut toml::Table)] = &[migrate_v1];
 
 /// Application configuration
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Version of the config file format, older files are migrated when loaded
+    #[serde(default = "default_version")]
+    pub version: u32,
     /// Directory to search for conflicts and duplicates
     #[serde(default = "default_working_directory")]
     pub working_directory: PathBuf,
     /// Command to use for moving files to trash
     #[serde(default = "default_trash_command")]
     pub trash_command: String,
+    /// Backend used to select files, detected automatically by default
+    #[serde(default)]
+    pub selector: SelectorKind,
+    /// Preset merge tool used to resolve conflicts, e.g. nvim, meld, kdiff3 or code
+    #[serde(default = "default_merge_tool")]
+    pub merge_tool: String,
+    /// Custom merge command, overrides `merge_tool`
+    ///
+    /// May use the placeholders `{local}`, `{remote}`, `{base}` and `{merged}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_command: Option<String>,
+    /// Treat a successful exit of the merge tool as resolved, like git mergetool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_trust_exit_code: Option<bool>,
+    /// File extensions scanned for conflicts
+    #[serde(default = "default_conflict_file_types")]
+    pub conflict_file_types: Vec<String>,
+    /// Strategy to resolve conflicts without asking, unless overridden on the command line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflicts: Option<ConflictStrategy>,
+    /// Strategy to resolve duplicates without asking, unless overridden on the command line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dupes: Option<DupeStrategy>,
+    /// Artifacts not modified for this long are stale, e.g. `12h` or `7d`
+    #[serde(default = "default_artifact_age")]
+    pub artifact_age: String,
+    /// Commit a snapshot before and the resolved state after changing a folder in a git repo,
+    /// untracked files are left alone
+    #[serde(default)]
+    pub git_commit: bool,
+    /// Synced folders to scan instead of `working_directory`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub folders: Vec<FolderConfig>,
+    /// Scan every folder shared by the local Syncthing instance
+    #[serde(default)]
+    pub discover: bool,
+    /// Location of Syncthing's config.xml, found automatically by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syncthing_config: Option<PathBuf>,
+    /// Pause folders through Syncthing's REST API while changing them and rescan afterwards
+    #[serde(default)]
+    pub syncthing_api: bool,
+    /// Address of the REST API, taken from config.xml by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syncthing_address: Option<String>,
+    /// API key of the REST API, taken from config.xml by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syncthing_api_key: Option<String>,
+    /// Commands run before and after scans and resolutions
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+}
+
+/// A synced folder, with settings overriding the global ones
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FolderConfig {
+    /// Profile name to select the folder with `--profile`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Directory to search for conflicts and duplicates
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_tool: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_trust_exit_code: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict_file_types: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflicts: Option<ConflictStrategy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dupes: Option<DupeStrategy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<bool>,
+    /// Only report conflicts and duplicates, never change files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+    /// Directory with old versions, relative to the folder unless absolute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versions_path: Option<PathBuf>,
+}
+
+/// Effective configuration of a single folder
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Profile name, or the folder path if the folder has no name
+    pub name: String,
+    pub config: Config,
+    /// Files in the folder must not be changed, e.g. Syncthing receive-only folders
+    pub read_only: bool,
+    /// Directory with old versions, skipped when scanning
+    pub versions_dir: PathBuf,
+}
+
+#[cfg(test)]
+impl Profile {
+    /// Writable profile of a folder in `directory` with the default settings
+    pub fn for_test(directory: &Path) -> Self {
+        Profile {
+            name: "test".to_string(),
+            config: Config {
+                working_directory: directory.to_path_buf(),
+                ..Default::default()
+            },
+            read_only: false,
+            versions_dir: directory.join(STVERSIONS_DIR),
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             working_directory: default_working_directory(),
             trash_command: default_trash_command(),
+            selector: SelectorKind::default(),
+            merge_tool: default_merge_tool(),
+            merge_command: None,
+            merge_trust_exit_code: None,
+            conflict_file_types: default_conflict_file_types(),
+            conflicts: None,
+            dupes: None,
+            artifact_age: default_artifact_age(),
+            git_commit: false,
+            folders: Vec::new(),
+            discover: false,
+            syncthing_config: None,
+            syncthing_api: false,
+            syncthing_address: None,
+            syncthing_api_key: None,
+            hooks: Hooks::default(),
         }
     }
 }
@@ -35,29 +246,305 @@ impl Config {
         Ok(Self::get_config_dir()?.join("config.toml"))
     }
 
-    /// Load configuration from disk, creating default config if it doesn't exist
-    pub fn load() -> Result<Self> {
-        let config_path = Self::get_config_path()?;
-        if !config_path.exists() {
-            Self::create_default_config()?;
+    /// Load the effective configuration
+    ///
+    /// See [`Config::layers`] for the sources and their precedence.
+    pub fn load(path: Option<&Path>, cli: toml::Table) -> Result<Self> {
+        Self::layers(path, cli)?.build()
+    }
+
+    /// Collect configuration values from all sources
+    ///
+    /// Later sources take precedence: defaults, the user config file (or `path`),
+    /// a project config file, `RESOLVETHING_*` environment variables and the command line.
+    pub fn layers(path: Option<&Path>, cli: toml::Table) -> Result<Layers> {
+        let mut layers = Layers::default();
+        layers.add(Self::default().to_table()?, Source::Default);
+
+        let user = match path {
+            Some(path) if !path.exists() => {
+                return Err(Error::NotFound {
+                    path: path.to_path_buf(),
+                })
+                .context("Config file missing");
+            }
+            Some(path) => Some(path.to_path_buf()),
+            None => Some(Self::get_config_path()?).filter(|path| path.exists()),
+        };
+        if let Some(user) = user {
+            let table = layers.read_file(&user)?;
+            layers.add(table, Source::User(user));
+        }
+
+        let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+        if let Some(project) = find_project_config(&current_dir) {
+            let mut table = layers.read_file(&project)?;
+            // A relative working directory is relative to the project config
+            if let Some(toml::Value::String(dir)) = table.get_mut("working_directory")
+                && let Some(parent) = project.parent()
+            {
+                *dir = parent
+                    .join(expand_path(Path::new(dir))?)
+                    .to_string_lossy()
+                    .to_string();
+            }
+            layers.add(table, Source::Project(project));
+        }
+
+        for (var, key, value) in env_values(std::env::vars()) {
+            layers.set(key, value, Source::Env(var));
+        }
+        layers.add(cli, Source::Cli);
+        Ok(layers)
+    }
+
+    /// Expand `~` and environment variables in all configured paths
+    pub fn expand_paths(&mut self) -> Result<()> {
+        self.working_directory = expand_path(&self.working_directory)?;
+        if let Some(path) = &self.syncthing_config {
+            self.syncthing_config = Some(expand_path(path)?);
         }
-        let toml_content =
-            std::fs::read_to_string(&config_path).context("Failed to read config file")?;
-        toml::from_str(&toml_content).context("Failed to parse config file")
+        for folder in &mut self.folders {
+            folder.path = expand_path(&folder.path)?;
+            if let Some(path) = &folder.versions_path {
+                folder.versions_path = Some(expand_path(path)?);
+            }
+        }
+        Ok(())
     }
 
-    /// Save configuration to disk
+    /// Find problems that would make a run fail, like missing directories or commands
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut report = |problem: String| {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        };
+        if let Some(path) = &self.syncthing_config
+            && !path.is_file()
+        {
+            report(format!(
+                "Syncthing config {} does not exist",
+                path.display()
+            ));
+        }
+        if self.discover && self.syncthing_config.is_none() && syncthing::find_config().is_none() {
+            report("discover is enabled, but Syncthing's config.xml was not found".to_string());
+        }
+        for profile in self.profiles() {
+            let config = &profile.config;
+            if !config.working_directory.is_dir() {
+                report(format!(
+                    "Folder {}: directory {} does not exist",
+                    profile.name,
+                    config.working_directory.display()
+                ));
+            }
+            match split_command(&config.trash_command) {
+                Ok(command) => match command.first() {
+                    Some(program) if which::which(program).is_err() => {
+                        report(format!("Trash command '{}' not found in PATH", program))
+                    }
+                    Some(_) => {}
+                    None => report(format!("Folder {}: trash command is empty", profile.name)),
+                },
+                Err(e) => report(format!("Folder {}: {:#}", profile.name, e)),
+            }
+            match MergeTool::from_config(config).map(|tool| tool.program()) {
+                Ok(Some(program)) if which::which(&program).is_err() => {
+                    report(format!("Merge tool '{}' not found in PATH", program))
+                }
+                Ok(_) => {}
+                Err(e) => report(format!("Folder {}: {:#}", profile.name, e)),
+            }
+            if let Err(e) = parse_age(&config.artifact_age) {
+                report(format!("{:#}", e));
+            }
+        }
+        problems
+    }
+
+    /// Serialize the configuration into a TOML table
+    pub fn to_table(&self) -> Result<toml::Table> {
+        toml::Table::try_from(self).context("Failed to serialize config")
+    }
+
+    /// Save configuration to the user config file
     pub fn save(&self) -> Result<()> {
-        let config_path = Self::get_config_path()?;
-        let config_dir = Self::get_config_dir()?;
-        if !config_dir.exists() {
-            std::fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
+        self.save_to(&Self::get_config_path()?)
+    }
+
+    /// Save configuration to `path`, with a comment explaining each setting
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("Failed to create config directory")?;
         }
-        let toml_content = toml::to_string(self).context("Failed to serialize config")?;
-        std::fs::write(&config_path, toml_content).context("Failed to write config file")?;
+        std::fs::write(path, self.to_commented_toml()?).context("Failed to write config file")?;
         Ok(())
     }
 
+    /// Serialize the configuration with a comment above each setting
+    pub fn to_commented_toml(&self) -> Result<String> {
+        let mut table = self.to_table()?;
+        let hooks = table.remove("hooks");
+        let folders = table.remove("folders");
+        let mut content = String::new();
+        for (key, comment) in KEY_COMMENTS {
+            let Some(value) = table.remove(*key) else {
+                continue;
+            };
+            content.push_str(&format!("# {}\n", comment));
+            let single = toml::Table::from_iter([(key.to_string(), value)]);
+            content.push_str(&toml::to_string(&single).context("Failed to serialize config")?);
+            content.push('\n');
+        }
+        // Tables have to come after all plain keys
+        if let Some(hooks) = hooks {
+            content.push_str("# Commands run around scans and resolutions, with JSON on stdin\n");
+            let hooks = toml::Table::from_iter([("hooks".to_string(), hooks)]);
+            content.push_str(&toml::to_string(&hooks).context("Failed to serialize config")?);
+            content.push('\n');
+        }
+        if let Some(folders) = folders {
+            content.push_str("# Synced folders, each may override the settings above\n");
+            let folders = toml::Table::from_iter([("folders".to_string(), folders)]);
+            content.push_str(&toml::to_string(&folders).context("Failed to serialize config")?);
+        }
+        Ok(content)
+    }
+
+    /// Effective configuration of every configured folder
+    ///
+    /// Without configured folders, the working directory is the only profile.
+    pub fn profiles(&self) -> Vec<Profile> {
+        if self.folders.is_empty() {
+            return vec![Profile {
+                name: self.working_directory.to_string_lossy().to_string(),
+                read_only: false,
+                versions_dir: self.working_directory.join(STVERSIONS_DIR),
+                config: Config {
+                    folders: Vec::new(),
+                    ..self.clone()
+                },
+            }];
+        }
+        self.folders
+            .iter()
+            .map(|folder| self.profile_for(folder))
+            .collect()
+    }
+
+    fn profile_for(&self, folder: &FolderConfig) -> Profile {
+        let base = self.clone();
+        let config = Config {
+            working_directory: folder.path.clone(),
+            trash_command: folder.trash_command.clone().unwrap_or(base.trash_command),
+            merge_tool: folder.merge_tool.clone().unwrap_or(base.merge_tool),
+            merge_command: folder.merge_command.clone().or(base.merge_command),
+            merge_trust_exit_code: folder.merge_trust_exit_code.or(base.merge_trust_exit_code),
+            conflict_file_types: folder
+                .conflict_file_types
+                .clone()
+                .unwrap_or(base.conflict_file_types),
+            conflicts: folder.conflicts.or(base.conflicts),
+            dupes: folder.dupes.or(base.dupes),
+            git_commit: folder.git_commit.unwrap_or(base.git_commit),
+            folders: Vec::new(),
+            ..base
+        };
+        Profile {
+            name: folder
+                .name
+                .clone()
+                .unwrap_or_else(|| folder.path.to_string_lossy().to_string()),
+            read_only: folder.read_only.unwrap_or(false),
+            versions_dir: folder.path.join(
+                folder
+                    .versions_path
+                    .as_deref()
+                    .unwrap_or(Path::new(STVERSIONS_DIR)),
+            ),
+            config,
+        }
+    }
+
+    /// Location of Syncthing's config.xml
+    pub fn syncthing_config_path(&self) -> Result<PathBuf> {
+        match &self.syncthing_config {
+            Some(path) => Ok(path.clone()),
+            None => syncthing::find_config().context("Could not find Syncthing's config.xml"),
+        }
+    }
+
+    /// Add the folders shared by the local Syncthing instance
+    pub fn discover_folders(&mut self) -> Result<()> {
+        self.add_discovered(syncthing::load(&self.syncthing_config_path()?)?);
+        Ok(())
+    }
+
+    /// Add discovered folders, configured settings of the same path take precedence
+    fn add_discovered(&mut self, discovered: Vec<SyncthingFolder>) {
+        for found in discovered.into_iter().filter(|folder| !folder.paused) {
+            let index = match self.folders.iter().position(|f| f.path == found.path) {
+                Some(index) => index,
+                None => {
+                    self.folders.push(FolderConfig {
+                        path: found.path.clone(),
+                        ..Default::default()
+                    });
+                    self.folders.len() - 1
+                }
+            };
+            let folder = &mut self.folders[index];
+            folder.name.get_or_insert_with(|| found.name().to_string());
+            folder.read_only.get_or_insert(found.is_read_only());
+            folder
+                .versions_path
+                .get_or_insert_with(|| found.versions_dir());
+        }
+    }
+
+    /// Profiles selected by the run options, including discovered Syncthing folders
+    pub fn run_profiles(&mut self, options: &RunOptions) -> Result<Vec<Profile>> {
+        if options.discover || self.discover {
+            self.discover_folders()
+                .context("Failed to discover Syncthing folders")?;
+        }
+        self.select_profiles(options.profile.as_deref(), options.folder.as_deref())
+    }
+
+    /// Select the profiles to run, by profile name or folder path
+    ///
+    /// A folder path that is not configured runs with the global settings.
+    pub fn select_profiles(
+        &self,
+        profile: Option<&str>,
+        folder: Option<&Path>,
+    ) -> Result<Vec<Profile>> {
+        let profiles = self.profiles();
+        if let Some(name) = profile {
+            let selected: Vec<Profile> = profiles.into_iter().filter(|p| p.name == name).collect();
+            if selected.is_empty() {
+                return Err(Error::UnknownProfile {
+                    name: name.to_string(),
+                });
+            }
+            return Ok(selected);
+        }
+        if let Some(folder) = folder {
+            let configured = self.folders.iter().find(|f| f.path == folder);
+            return Ok(vec![match configured {
+                Some(configured) => self.profile_for(configured),
+                None => self.profile_for(&FolderConfig {
+                    path: folder.to_path_buf(),
+                    ..Default::default()
+                }),
+            }]);
+        }
+        Ok(profiles)
+    }
+
     /// Create a default configuration file
     pub fn create_default_config() -> Result<()> {
         let config = Self::default();
@@ -65,6 +552,179 @@ impl Config {
     }
 }
 
+/// Where a configuration value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    User(PathBuf),
+    Project(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::User(path) => write!(f, "user config {}", path.display()),
+            Source::Project(path) => write!(f, "project config {}", path.display()),
+            Source::Env(var) => write!(f, "environment variable {}", var),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Configuration values merged from several sources
+///
+/// Values set later replace earlier ones, each top-level key remembers its source.
+#[derive(Debug, Default)]
+pub struct Layers {
+    values: toml::Table,
+    sources: BTreeMap<String, Source>,
+    /// Config files migrated from an older version, with that version
+    migrated: Vec<(PathBuf, u32)>,
+}
+
+impl Layers {
+    /// Read a config file, migrating it to the current version
+    fn read_file(&mut self, path: &Path) -> Result<toml::Table> {
+        let invalid = || format!("Invalid config file {}", path.display());
+        let content = std::fs::read_to_string(path).with_context(invalid)?;
+        let parse_error = |e: toml::de::Error| Error::config_parse(path, &content, &e);
+        let mut table: toml::Table = toml::from_str(&content).map_err(parse_error)?;
+        let version = file_version(&table).with_context(invalid)?;
+        if version == CONFIG_VERSION {
+            // Parse the file itself for errors pointing at the line
+            toml::from_str::<Config>(&content).map_err(parse_error)?;
+            return Ok(table);
+        }
+        migrate(&mut table, version);
+        if let Err(e) = toml::Value::Table(table.clone()).try_into::<Config>() {
+            // Prefer the error pointing at the line if the original has it too
+            toml::from_str::<Config>(&content).map_err(parse_error)?;
+            return Err(parse_error(e));
+        }
+        self.migrated.push((path.to_path_buf(), version));
+        Ok(table)
+    }
+
+    /// Config files migrated from an older version, with that version
+    pub fn migrated(&self) -> &[(PathBuf, u32)] {
+        &self.migrated
+    }
+
+    /// Set a single value
+    pub fn set(&mut self, key: String, value: toml::Value, source: Source) {
+        self.sources.insert(key.clone(), source);
+        self.values.insert(key, value);
+    }
+
+    /// Set all values of a table
+    pub fn add(&mut self, table: toml::Table, source: Source) {
+        for (key, value) in table {
+            self.set(key, value, source.clone());
+        }
+    }
+
+    /// Source of a top-level key
+    pub fn source(&self, key: &str) -> &Source {
+        self.sources.get(key).unwrap_or(&Source::Default)
+    }
+
+    /// Build the effective configuration
+    pub fn build(&self) -> Result<Config> {
+        let mut config: Config = toml::Value::Table(self.values.clone())
+            .try_into()
+            .context("Invalid configuration")?;
+        config.expand_paths()?;
+        Ok(config)
+    }
+}
+
+/// Version of a config file, rejecting files written by a newer release
+fn file_version(table: &toml::Table) -> Result<u32> {
+    let version = match table.get("version") {
+        // Files without a version predate the key
+        None => 1,
+        Some(toml::Value::Integer(version)) if *version >= 1 => *version as u32,
+        Some(other) => bail!("Invalid config version {}", other),
+    };
+    if version > CONFIG_VERSION {
+        bail!(
+            "Config version {} is newer than the supported version {}, please update resolvething",
+            version,
+            CONFIG_VERSION
+        );
+    }
+    Ok(version)
+}
+
+/// Migrate a config file from `from_version` to the current version
+fn migrate(table: &mut toml::Table, from_version: u32) {
+    for migration in &MIGRATIONS[from_version as usize - 1..] {
+        migration(table);
+    }
+    table.insert("version".to_string(), (CONFIG_VERSION as i64).into());
+}
+
+/// Version 1 only knew `working_directory` and `trash_command`, both are unchanged
+fn migrate_v1(_table: &mut toml::Table) {}
+
+/// Expand a leading `~` and `$VAR` or `${VAR}` in a path
+pub fn expand_path(path: &Path) -> Result<PathBuf> {
+    let path = path.to_string_lossy();
+    let variable = regex::Regex::new(r"\$(?:\{(\w+)\}|(\w+))").expect("valid regex");
+    let mut expanded = String::new();
+    let mut last = 0;
+    for captures in variable.captures_iter(&path) {
+        let all = captures.get(0).expect("whole match");
+        let name = captures.get(1).or(captures.get(2)).expect("variable name");
+        let value = std::env::var(name.as_str()).with_context(|| {
+            format!(
+                "Environment variable {} used in {} is not set",
+                name.as_str(),
+                path
+            )
+        })?;
+        expanded.push_str(&path[last..all.start()]);
+        expanded.push_str(&value);
+        last = all.end();
+    }
+    expanded.push_str(&path[last..]);
+
+    if expanded == "~" || expanded.starts_with("~/") {
+        let home = dirs::home_dir().context("Could not determine home directory")?;
+        return Ok(home.join(expanded[1..].trim_start_matches('/')));
+    }
+    Ok(PathBuf::from(expanded))
+}
+
+/// Find the closest project config in `dir` or one of its parents
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Config values set by `RESOLVETHING_*` environment variables
+///
+/// Values are parsed as TOML, e.g. `true` or `["md", "txt"]`, and taken as strings otherwise.
+fn env_values(vars: impl Iterator<Item = (String, String)>) -> Vec<(String, String, toml::Value)> {
+    vars.filter_map(|(var, raw)| {
+        let key = var.strip_prefix(ENV_PREFIX)?.to_lowercase();
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", raw))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or(toml::Value::String(raw));
+        Some((var, key, value))
+    })
+    .collect()
+}
+
+fn default_version() -> u32 {
+    CONFIG_VERSION
+}
+
 fn default_working_directory() -> PathBuf {
     dirs::home_dir()
         .unwrap_or_else(|| PathBuf::from("."))
@@ -74,3 +734,259 @@ fn default_working_directory() -> PathBuf {
 fn default_trash_command() -> String {
     "trash".to_string()
 }
+
+fn default_artifact_age() -> String {
+    "1d".to_string()
+}
+
+fn default_merge_tool() -> String {
+    "nvim".to_string()
+}
+
+fn default_conflict_file_types() -> Vec<String> {
+    vec!["md".to_string(), "json".to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            trash_command = "trash"
+            merge_tool = "nvim"
+
+            [[folders]]
+            name = "wiki"
+            path = "/sync/wiki"
+            merge_tool = "meld"
+            conflict_file_types = ["md", "txt"]
+            conflicts = "keep-newest"
+
+            [[folders]]
+            path = "/sync/photos"
+            trash_command = "gio trash"
+            dupes = "skip"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_profiles_apply_overrides() {
+        let profiles = config().profiles();
+        assert_eq!(profiles.len(), 2);
+
+        assert_eq!(profiles[0].name, "wiki");
+        assert_eq!(
+            profiles[0].config.working_directory,
+            PathBuf::from("/sync/wiki")
+        );
+        assert_eq!(profiles[0].config.merge_tool, "meld");
+        assert_eq!(profiles[0].config.trash_command, "trash");
+        assert_eq!(profiles[0].config.conflict_file_types, vec!["md", "txt"]);
+        assert_eq!(
+            profiles[0].config.conflicts,
+            Some(ConflictStrategy::KeepNewest)
+        );
+
+        assert_eq!(profiles[1].name, "/sync/photos");
+        assert_eq!(profiles[1].config.merge_tool, "nvim");
+        assert_eq!(profiles[1].config.trash_command, "gio trash");
+        assert_eq!(profiles[1].config.conflict_file_types, vec!["md", "json"]);
+        assert_eq!(profiles[1].config.dupes, Some(DupeStrategy::Skip));
+    }
+
+    #[test]
+    fn test_profiles_default_to_working_directory() {
+        let config = Config::default();
+        let profiles = config.profiles();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(
+            profiles[0].config.working_directory,
+            config.working_directory
+        );
+    }
+
+    #[test]
+    fn test_add_discovered() {
+        let mut config = config();
+        let discovered = syncthing::parse(
+            r#"<configuration>
+                <folder id="w" label="Notes" path="/sync/wiki" type="sendreceive"/>
+                <folder id="m" label="Music" path="/sync/music" type="receiveonly">
+                    <versioning type="trashcan"><fsPath>/trash</fsPath></versioning>
+                </folder>
+                <folder id="p" path="/sync/paused"><paused>true</paused></folder>
+            </configuration>"#,
+        )
+        .unwrap();
+        config.add_discovered(discovered);
+
+        let profiles = config.profiles();
+        assert_eq!(profiles.len(), 3);
+        assert_eq!(profiles[0].name, "wiki");
+        assert!(!profiles[0].read_only);
+        assert_eq!(
+            profiles[0].versions_dir,
+            PathBuf::from("/sync/wiki/.stversions")
+        );
+        assert_eq!(profiles[2].name, "Music");
+        assert!(profiles[2].read_only);
+        assert_eq!(profiles[2].versions_dir, PathBuf::from("/trash"));
+    }
+
+    #[test]
+    fn test_layers_precedence() {
+        let mut layers = Layers::default();
+        layers.add(Config::default().to_table().unwrap(), Source::Default);
+        layers.add(
+            toml::from_str(r#"merge_tool = "meld""#).unwrap(),
+            Source::User(PathBuf::from("config.toml")),
+        );
+        for (var, key, value) in env_values(
+            [
+                ("RESOLVETHING_MERGE_TOOL".to_string(), "kdiff3".to_string()),
+                ("RESOLVETHING_DISCOVER".to_string(), "true".to_string()),
+                ("HOME".to_string(), "/home/me".to_string()),
+            ]
+            .into_iter(),
+        ) {
+            layers.set(key, value, Source::Env(var));
+        }
+        layers.add(toml::from_str(r#"discover = false"#).unwrap(), Source::Cli);
+
+        let config = layers.build().unwrap();
+        assert_eq!(config.merge_tool, "kdiff3");
+        assert!(!config.discover);
+        assert_eq!(
+            *layers.source("merge_tool"),
+            Source::Env("RESOLVETHING_MERGE_TOOL".to_string())
+        );
+        assert_eq!(*layers.source("discover"), Source::Cli);
+        assert_eq!(*layers.source("trash_command"), Source::Default);
+    }
+
+    #[test]
+    fn test_env_values() {
+        let values = env_values(
+            [(
+                "RESOLVETHING_CONFLICT_FILE_TYPES".to_string(),
+                r#"["md", "txt"]"#.to_string(),
+            )]
+            .into_iter(),
+        );
+        assert_eq!(values[0].1, "conflict_file_types");
+        assert_eq!(
+            values[0].2,
+            toml::Value::Array(vec!["md".into(), "txt".into()])
+        );
+    }
+
+    #[test]
+    fn test_commented_toml_roundtrip() {
+        let mut config = config();
+        config.hooks.post_run = Some("notify-send resolvething done".to_string());
+        let content = config.to_commented_toml().unwrap();
+        assert!(content.contains("# Merge tool preset"));
+        assert!(content.contains("[hooks]"));
+        assert!(content.contains("[[folders]]"));
+        let parsed: Config = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.to_table().unwrap(), config.to_table().unwrap());
+    }
+
+    #[test]
+    fn test_file_version() {
+        let old: toml::Table = toml::from_str(r#"trash_command = "trash""#).unwrap();
+        assert_eq!(file_version(&old).unwrap(), 1);
+        let current: toml::Table = toml::from_str("version = 2").unwrap();
+        assert_eq!(file_version(&current).unwrap(), CONFIG_VERSION);
+
+        let newer: toml::Table = toml::from_str("version = 99").unwrap();
+        assert!(file_version(&newer).is_err());
+        let invalid: toml::Table = toml::from_str("version = 0").unwrap();
+        assert!(file_version(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_migrate_v1_file() {
+        let dir = TempDir::new("config-migrate");
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "working_directory = \"/sync\"\ntrash_command = \"gio trash\"\n",
+        )
+        .unwrap();
+
+        let mut layers = Layers::default();
+        let table = layers.read_file(&path).unwrap();
+        assert_eq!(
+            table["version"],
+            toml::Value::Integer(CONFIG_VERSION as i64)
+        );
+        assert_eq!(table["trash_command"].as_str(), Some("gio trash"));
+        assert_eq!(layers.migrated(), [(path.clone(), 1)]);
+
+        // files of the current version are read as they are
+        std::fs::write(&path, format!("version = {}\n", CONFIG_VERSION)).unwrap();
+        let mut layers = Layers::default();
+        layers.read_file(&path).unwrap();
+        assert!(layers.migrated().is_empty());
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_path(Path::new("~/wiki")).unwrap(), home.join("wiki"));
+        assert_eq!(
+            expand_path(Path::new("/a/~/b")).unwrap(),
+            PathBuf::from("/a/~/b")
+        );
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(
+            expand_path(Path::new("${PATH}/x")).unwrap(),
+            PathBuf::from(format!("{}/x", path))
+        );
+        assert!(expand_path(Path::new("$RESOLVETHING_UNSET_VARIABLE/x")).is_err());
+    }
+
+    #[test]
+    fn test_check_trash_command_with_arguments() {
+        let mut config: Config = toml::from_str(
+            r#"
+            trash_command = "sh -c true"
+            merge_command = "sh -c true"
+            "#,
+        )
+        .unwrap();
+        config.working_directory = std::env::temp_dir();
+        assert_eq!(config.check(), Vec::<String>::new());
+
+        config.trash_command = "resolvething-missing --trash".to_string();
+        assert_eq!(
+            config.check(),
+            ["Trash command 'resolvething-missing' not found in PATH"]
+        );
+    }
+
+    #[test]
+    fn test_select_profiles() {
+        let config = config();
+        let wiki = config.select_profiles(Some("wiki"), None).unwrap();
+        assert_eq!(wiki.len(), 1);
+        assert_eq!(wiki[0].config.merge_tool, "meld");
+
+        assert!(config.select_profiles(Some("missing"), None).is_err());
+
+        let adhoc = config
+            .select_profiles(None, Some(Path::new("/elsewhere")))
+            .unwrap();
+        assert_eq!(
+            adhoc[0].config.working_directory,
+            PathBuf::from("/elsewhere")
+        );
+        assert_eq!(adhoc[0].config.merge_tool, "nvim");
+    }
+}
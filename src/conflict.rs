@@ -1,49 +1,121 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
-use walkdir::WalkDir;
+use chrono::NaiveDateTime;
+use serde::{Serialize, Serializer};
 
 use crate::{
-    config::Config, diff::VimDiff, sync_conflict_regex_for_type,
-    sync_conflict_replace_regex_for_type, trash::Trash,
+    Context, Error, Result,
+    batch::{ConflictStrategy, merge_superset},
+    config::Config,
+    config::Profile,
+    diff::{DiffLine, MergeFiles, MergeOutcome, MergeTool, line_diff},
+    rename_file,
+    scan::{FileKind, FolderScan},
+    selector::Selector,
+    sync_conflict_info_regex, sync_conflict_regex_for_type, sync_conflict_replace_regex_for_type,
+    syncthing::STVERSIONS_DIR,
+    trash::Trash,
+    tui::{Action, Progress},
+    versions::{Version, restore},
 };
 
 /// Maximum file size (in bytes) to process for conflict resolution
 const MAX_FILE_SIZE: u64 = 1_000_000;
 
-/// Name of the Syncthing versions directory to skip
-const STVERSIONS_DIR: &str = ".stversions";
+/// Bytes searched for a NUL byte to tell binary from text files, as git does
+const BINARY_CHECK_BYTES: u64 = 8000;
+
+/// Answers offered for an orphaned conflict copy
+const RESTORE_ENTRY: &str = "restore as original";
+const TRASH_ENTRY: &str = "trash conflict copy";
+const SKIP_ENTRY: &str = "skip";
+const QUIT_ENTRY: &str = "quit";
+
+/// Share of common lines above which a file counts as the renamed original of an orphan
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// Size ratio above which a file is not diffed against an orphan
+///
+/// At half of the lines in common, one file has at most three times the lines of the other.
+const MAX_SIZE_RATIO: u64 = 3;
 
 /// Represents a conflict between an original file and a modified version
+#[derive(Serialize)]
 pub struct Conflict {
+    #[serde(rename = "original")]
     pub originalfile: String,
+    #[serde(rename = "modified")]
     pub modifiedfile: String,
+    /// Local time the conflict was detected, from the name of the conflict copy
+    #[serde(serialize_with = "serialize_time")]
+    pub timestamp: Option<NaiveDateTime>,
+    /// Short ID of the device that created the conflict copy
+    pub device: Option<String>,
+    /// Sizes in bytes as found by the scan, `None` if unknown
+    pub original_size: Option<u64>,
+    pub modified_size: Option<u64>,
+}
+
+/// What to do with a conflict copy whose original is missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanAction {
+    /// Move the copy back to the name of the original
+    Restore,
+    /// Resolve the copy against a file that looks like the renamed original
+    Relink,
+    Trash,
+    Skip,
+    Quit,
 }
 
 impl Conflict {
     pub fn new(originalfile: String, modifiedfile: String) -> Self {
+        let captures = sync_conflict_info_regex().captures(&modifiedfile);
+        let timestamp = captures.as_ref().and_then(|captures| {
+            NaiveDateTime::parse_from_str(
+                &format!("{}{}", &captures[1], &captures[2]),
+                "%Y%m%d%H%M%S",
+            )
+            .ok()
+        });
+        let device = captures.map(|captures| captures[3].to_string());
         Conflict {
             originalfile,
             modifiedfile,
+            timestamp,
+            device,
+            original_size: None,
+            modified_size: None,
         }
     }
+
+    /// Set the sizes of both files, e.g. from a scan
+    pub fn with_sizes(mut self, original_size: Option<u64>, modified_size: Option<u64>) -> Self {
+        self.original_size = original_size;
+        self.modified_size = modified_size;
+        self
+    }
+
     pub fn print(&self) {
-        println!("Original file: {}", self.originalfile);
-        println!("Modified file: {}", self.modifiedfile);
+        eprintln!("Original file: {}", self.originalfile);
+        eprintln!("Modified file: {}", self.modifiedfile);
     }
 
+    /// Whether a file is a small text file, judged by its size and its first bytes
     pub fn file_is_valid(file: &str) -> bool {
-        let path = PathBuf::from(file);
-        path.exists()
-            && path.is_file()
-            && std::fs::metadata(&path)
-                .ok()
-                .map(|m| m.len() < MAX_FILE_SIZE)
-                .unwrap_or(false)
-            && std::fs::read(&path)
-                .ok()
-                .map(|content| !content.contains(&0))
-                .unwrap_or(false)
+        let Ok(metadata) = std::fs::metadata(file) else {
+            return false;
+        };
+        if !metadata.is_file() || metadata.len() >= MAX_FILE_SIZE {
+            return false;
+        }
+        let mut head = Vec::new();
+        std::fs::File::open(file)
+            .and_then(|f| f.take(BINARY_CHECK_BYTES).read_to_end(&mut head))
+            .is_ok()
+            && !head.contains(&0)
     }
 
     pub fn is_valid(&self) -> bool {
@@ -52,85 +124,317 @@ impl Conflict {
             && Conflict::file_is_valid(&self.modifiedfile)
     }
 
-    pub fn handle_conflict(&self, config: &Config) -> Result<()> {
+    /// Whether the conflict copy survived but the original was deleted or renamed
+    pub fn is_orphan(&self) -> bool {
+        !Path::new(&self.originalfile).exists() && Conflict::file_is_valid(&self.modifiedfile)
+    }
+
+    /// Find the file most similar to an orphaned copy among the files of its folder
+    ///
+    /// Only text files with the same extension and a similar size are compared.
+    /// Returns the file and the share of common lines.
+    pub fn find_renamed_original(&self, scan: &FolderScan) -> Option<(String, f64)> {
+        let copy = std::fs::read_to_string(&self.modifiedfile).ok()?;
+        let extension = Path::new(&self.originalfile).extension();
+        let size = copy.len() as u64;
+        scan.of_kind(FileKind::Regular)
+            .filter(|file| file.size < MAX_FILE_SIZE && file.path.extension() == extension)
+            .filter(|file| {
+                file.size.max(size) <= file.size.min(size).saturating_mul(MAX_SIZE_RATIO)
+            })
+            .filter_map(|file| file.path.to_str().map(str::to_string))
+            .filter(|path| Conflict::file_is_valid(path))
+            .filter_map(|path| {
+                let content = std::fs::read_to_string(&path).ok()?;
+                Some((path, similarity(&copy, &content)))
+            })
+            .filter(|(_, score)| *score >= MIN_SIMILARITY)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    /// Let the user decide what to do with an orphaned copy
+    ///
+    /// `renamed` is a file that may be the renamed original and its similarity to the copy.
+    pub fn choose_orphan(
+        &self,
+        selector: &dyn Selector,
+        renamed: Option<&(String, f64)>,
+    ) -> Result<OrphanAction> {
+        let mut items = vec![format!("{}: {}", RESTORE_ENTRY, self.originalfile)];
+        if let Some((file, score)) = renamed {
+            items.push(format!(
+                "merge with renamed original {} ({:.0}% similar)",
+                file,
+                score * 100.0
+            ));
+        }
+        items.extend([TRASH_ENTRY, SKIP_ENTRY, QUIT_ENTRY].map(String::from));
+        let prompt = format!("Original of {} is missing", self.modifiedfile);
+        let Some(index) = selector.select(&prompt, &items)? else {
+            return Ok(OrphanAction::Skip);
+        };
+        let index = match renamed {
+            Some(_) if index == 1 => return Ok(OrphanAction::Relink),
+            Some(_) if index > 1 => index - 1,
+            _ => index,
+        };
+        Ok(match index {
+            0 => OrphanAction::Restore,
+            1 => OrphanAction::Trash,
+            2 => OrphanAction::Skip,
+            _ => OrphanAction::Quit,
+        })
+    }
+
+    /// Apply a decision about an orphaned copy, `Relink` is left to the caller
+    pub fn apply_orphan(&self, action: OrphanAction, config: &Config) -> Result<Option<String>> {
+        match action {
+            OrphanAction::Restore => self.restore_orphan(),
+            OrphanAction::Trash => {
+                Trash::trash(&self.modifiedfile, config)?;
+                Ok(Some("trashed orphaned conflict copy".to_string()))
+            }
+            OrphanAction::Relink | OrphanAction::Skip | OrphanAction::Quit => Ok(None),
+        }
+    }
+
+    /// Move an orphaned copy back to the name of the original
+    fn restore_orphan(&self) -> Result<Option<String>> {
+        if let Some(parent) = Path::new(&self.originalfile).parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::rename(&self.modifiedfile, &self.originalfile).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                self.modifiedfile, self.originalfile
+            )
+        })?;
+        Ok(Some("restored conflict copy as original".to_string()))
+    }
+
+    /// Paths of both versions, the original first
+    pub fn files(&self) -> Vec<String> {
+        vec![self.originalfile.clone(), self.modifiedfile.clone()]
+    }
+
+    /// Let the user decide how to resolve the conflict
+    pub fn choose(&self, selector: &dyn Selector, progress: Progress) -> Result<Action> {
         if !self.is_valid() {
-            return Ok(());
+            return Ok(Action::Skip);
         }
 
         if !PathBuf::from(&self.modifiedfile).exists() && PathBuf::from(&self.originalfile).exists()
         {
-            println!("conflict already resolved:");
+            eprintln!("conflict already resolved:");
             self.print();
-            println!();
-            return Ok(());
+            eprintln!();
+            return Ok(Action::Skip);
         }
-        VimDiff::diff(&self.modifiedfile, &self.originalfile)?;
-
-        let resolved = if let (Ok(original_content), Ok(modified_content)) = (
-            std::fs::read_to_string(&self.originalfile),
-            std::fs::read_to_string(&self.modifiedfile),
-        ) {
-            // Compare the contents
-            original_content == modified_content
-        } else {
-            // If we can't read either file, they're not resolved
-            false
-        };
-        if resolved {
-            Trash::trash(&self.modifiedfile, config)?;
+
+        selector.choose("Conflict", &self.files(), progress, true)
+    }
+
+    /// Apply a decision made in a selector
+    ///
+    /// Returns a description of what was done, or `None` if the conflict is left pending,
+    /// e.g. because the merge was aborted or both files were kept.
+    pub fn apply(&self, action: &Action, config: &Config) -> Result<Option<String>> {
+        match action {
+            Action::Keep(0) => {
+                Trash::trash(&self.modifiedfile, config)?;
+                Ok(Some("kept original".to_string()))
+            }
+            Action::Keep(_) => {
+                self.keep_modified(config)?;
+                Ok(Some("kept conflict copy".to_string()))
+            }
+            Action::Rename(index, name) => {
+                self.apply(&Action::Keep(*index), config)?;
+                let renamed = rename_file(&self.originalfile, name)?;
+                Ok(Some(format!("kept as {}", renamed)))
+            }
+            Action::KeepSeveral(_) | Action::KeepAll => Ok(None),
+            Action::Merge(_) => Ok(match self.merge(config)? {
+                MergeOutcome::Resolved => Some("merged".to_string()),
+                MergeOutcome::Aborted => None,
+            }),
+            Action::History | Action::Skip | Action::Quit => Ok(None),
         }
-        Ok(())
     }
+
+    /// Resolve the conflict without asking, as far as the strategy allows
+    ///
+    /// Returns a description of what was done, or `None` if the conflict is left pending.
+    pub fn resolve_with(
+        &self,
+        strategy: ConflictStrategy,
+        config: &Config,
+    ) -> Result<Option<&'static str>> {
+        match strategy {
+            ConflictStrategy::Skip => Ok(None),
+            ConflictStrategy::KeepOriginal => {
+                Trash::trash(&self.modifiedfile, config)?;
+                Ok(Some("kept original"))
+            }
+            ConflictStrategy::KeepNewest => {
+                let modified = |file: &str| std::fs::metadata(file).and_then(|m| m.modified()).ok();
+                match (modified(&self.originalfile), modified(&self.modifiedfile)) {
+                    (Some(original), Some(copy)) if copy > original => {
+                        self.keep_modified(config)?;
+                        Ok(Some("kept newer conflict copy"))
+                    }
+                    (Some(original), Some(copy)) if original > copy => {
+                        Trash::trash(&self.modifiedfile, config)?;
+                        Ok(Some("kept newer original"))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            ConflictStrategy::AutoMerge => {
+                let original = std::fs::read_to_string(&self.originalfile)
+                    .with_context(|| format!("Failed to read {}", self.originalfile))?;
+                let modified = std::fs::read_to_string(&self.modifiedfile)
+                    .with_context(|| format!("Failed to read {}", self.modifiedfile))?;
+                match merge_superset(&original, &modified) {
+                    Some(merged) if merged == original => {
+                        Trash::trash(&self.modifiedfile, config)?;
+                        Ok(Some("merged, original contains all changes"))
+                    }
+                    Some(_) => {
+                        self.keep_modified(config)?;
+                        Ok(Some("merged, conflict copy contains all changes"))
+                    }
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Resolve the conflict with an old version of the original, trashing both current files
+    pub fn restore_version(&self, version: &Version, config: &Config) -> Result<Option<String>> {
+        restore(version, Path::new(&self.originalfile), config)?;
+        Trash::trash(&self.modifiedfile, config)?;
+        Ok(Some(format!("restored version from {}", version.timestamp)))
+    }
+
+    /// Replace the original file with the modified copy
+    fn keep_modified(&self, config: &Config) -> Result<()> {
+        Trash::trash(&self.originalfile, config)?;
+        std::fs::rename(&self.modifiedfile, &self.originalfile).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                self.modifiedfile, self.originalfile
+            )
+        })
+    }
+
+    /// Merge both files in the merge tool, trash the copy once resolved
+    fn merge(&self, config: &Config) -> Result<MergeOutcome> {
+        let tool = MergeTool::from_config(config)?;
+        let outcome = tool.merge(&MergeFiles {
+            local: &self.originalfile,
+            remote: &self.modifiedfile,
+            base: None,
+            merged: &self.originalfile,
+        })?;
+        match outcome {
+            MergeOutcome::Resolved => Trash::trash(&self.modifiedfile, config)?,
+            MergeOutcome::Aborted => eprintln!("merge aborted: {}", self.originalfile),
+        }
+        Ok(outcome)
+    }
+}
+
+/// Share of lines two texts have in common, from 0 to 1
+fn similarity(a: &str, b: &str) -> f64 {
+    let total = a.lines().count() + b.lines().count();
+    if total == 0 {
+        return 1.0;
+    }
+    let Some(diff) = line_diff(a, b) else {
+        return 0.0;
+    };
+    let same = diff
+        .iter()
+        .filter(|line| matches!(line, DiffLine::Same(_)))
+        .count();
+    (2 * same) as f64 / total as f64
+}
+
+fn serialize_time<S: Serializer>(
+    time: &Option<NaiveDateTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    time.map(|time| time.format("%Y-%m-%dT%H:%M:%S").to_string())
+        .serialize(serializer)
 }
 
 /// Finds and manages Syncthing conflict files in a directory
 pub struct ConflictFinder {
     pub directory: String,
+    /// Directory with old versions, skipped when searching
+    pub versions_dir: PathBuf,
     pub conflicts: Vec<Conflict>,
 }
 
 impl ConflictFinder {
     pub fn new(directory: String) -> Self {
         ConflictFinder {
+            versions_dir: Path::new(&directory).join(STVERSIONS_DIR),
             directory,
             conflicts: Vec::new(),
         }
     }
 
-    pub fn find_conflicts(&mut self, file_type: &str) -> Result<()> {
-        // walkdir across directory, find
+    /// Use a versions directory other than `.stversions`
+    pub fn with_versions_dir(mut self, versions_dir: PathBuf) -> Self {
+        self.versions_dir = versions_dir;
+        self
+    }
+
+    /// Find the conflicts of all configured file types in a folder
+    pub fn for_profile(profile: &Profile) -> Result<Self> {
+        Self::from_scan(profile, &FolderScan::new(profile)?)
+    }
+
+    /// Find the conflicts of all configured file types among the files of a scan
+    pub fn from_scan(profile: &Profile, scan: &FolderScan) -> Result<Self> {
+        let config = &profile.config;
+        let mut finder =
+            ConflictFinder::new(config.working_directory.to_string_lossy().to_string())
+                .with_versions_dir(profile.versions_dir.clone());
+        for file_type in &config.conflict_file_types {
+            finder.find_conflicts(scan, file_type)?;
+        }
+        Ok(finder)
+    }
+
+    /// Add the conflict copies of one file type among the files of a scan
+    pub fn find_conflicts(&mut self, scan: &FolderScan, file_type: &str) -> Result<()> {
         let regex = sync_conflict_regex_for_type(file_type);
         let replaceexp = sync_conflict_replace_regex_for_type(file_type);
-        for entry in WalkDir::new(&self.directory)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry
-                .path()
-                .components()
-                .filter_map(|comp| comp.as_os_str().to_str())
-                .any(|segment| segment == STVERSIONS_DIR)
-            {
-                println!("skipping stversions file {}", entry.path().display());
-                continue;
-            }
-            if entry.file_type().is_file() {
-                let path_str = entry
-                    .path()
-                    .to_str()
-                    .context("Invalid UTF-8 in file path")?;
-
-                if regex.is_match(path_str) {
-                    let originalfile = replaceexp
-                        .replace_all(path_str, &format!(".{}", file_type))
-                        .to_string();
-                    let modifiedfile = path_str.to_string();
-
-                    self.conflicts.push(Conflict {
-                        originalfile,
-                        modifiedfile,
-                    });
-                }
+        let sizes: HashMap<&Path, u64> = scan
+            .files
+            .iter()
+            .map(|file| (file.path.as_path(), file.size))
+            .collect();
+        for file in scan.conflicts() {
+            let path_str = file.path.to_str().ok_or_else(|| Error::InvalidPath {
+                path: file.path.clone(),
+            })?;
+
+            if regex.is_match(path_str) {
+                let originalfile = replaceexp
+                    .replace_all(path_str, &format!(".{}", file_type))
+                    .to_string();
+                let original_size = sizes.get(Path::new(&originalfile)).copied();
+                let modifiedfile = path_str.to_string();
+
+                self.conflicts.push(
+                    Conflict::new(originalfile, modifiedfile)
+                        .with_sizes(original_size, Some(file.size)),
+                );
             }
         }
         Ok(())
@@ -142,3 +446,70 @@ impl ConflictFinder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("a\nb\n", "a\nb\n"), 1.0);
+        assert_eq!(similarity("a\nb\nc\nd\n", "a\nb\nx\ny\n"), 0.5);
+        assert_eq!(similarity("a\n", "b\n"), 0.0);
+        assert_eq!(similarity("", ""), 1.0);
+    }
+
+    #[test]
+    fn test_find_renamed_original() {
+        let dir = TempDir::new("orphan");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let copy = dir.join("notes.sync-conflict-20240101-120000-ABCDEFG.md");
+        std::fs::write(&copy, "one\ntwo\nthree\nfour\n").unwrap();
+        std::fs::write(dir.join("sub/renamed.md"), "one\ntwo\nthree\nfive\n").unwrap();
+        std::fs::write(dir.join("other.md"), "unrelated\n").unwrap();
+        std::fs::write(dir.join("renamed.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+        let profile = Profile::for_test(&dir);
+        let conflict = Conflict::new(
+            dir.join("notes.md").to_string_lossy().to_string(),
+            copy.to_string_lossy().to_string(),
+        );
+
+        assert!(conflict.is_orphan());
+        let scan = FolderScan::new(&profile).unwrap();
+        let (file, score) = conflict.find_renamed_original(&scan).unwrap();
+        assert_eq!(Path::new(&file), dir.join("sub/renamed.md"));
+        assert_eq!(score, 0.75);
+    }
+
+    #[test]
+    fn test_from_scan_sizes() {
+        let dir = TempDir::new("conflict-sizes");
+        std::fs::write(dir.join("a.md"), "one\n").unwrap();
+        std::fs::write(
+            dir.join("a.sync-conflict-20240101-120000-ABCDEFG.md"),
+            "two\n2\n",
+        )
+        .unwrap();
+        let profile = Profile::for_test(&dir);
+
+        let finder =
+            ConflictFinder::from_scan(&profile, &FolderScan::new(&profile).unwrap()).unwrap();
+        assert_eq!(finder.conflicts.len(), 1);
+        assert_eq!(finder.conflicts[0].original_size, Some(4));
+        assert_eq!(finder.conflicts[0].modified_size, Some(6));
+    }
+
+    #[test]
+    fn test_conflict_info() {
+        let conflict = Conflict::new(
+            "/nonexistent/Umzug.md".to_string(),
+            "/nonexistent/Umzug.sync-conflict-20250412-111252-VNNIL2P.md".to_string(),
+        );
+        assert_eq!(conflict.device.as_deref(), Some("VNNIL2P"));
+        assert_eq!(
+            serde_json::to_string(&conflict).unwrap(),
+            r#"{"original":"/nonexistent/Umzug.md","modified":"/nonexistent/Umzug.sync-conflict-20250412-111252-VNNIL2P.md","timestamp":"2025-04-12T11:12:52","device":"VNNIL2P","original_size":null,"modified_size":null}"#
+        );
+    }
+}
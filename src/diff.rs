@@ -1,21 +1,481 @@
-use anyhow::{Context, Result};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
+use std::time::SystemTime;
 
-pub struct VimDiff;
+use crate::config::Config;
+use crate::{Context, Error, Result, bail};
 
-impl VimDiff {
-    pub fn diff(file1: &str, file2: &str) -> Result<()> {
-        Command::new("nvim")
-            .arg("-d")
-            .arg(file1)
-            .arg(file2)
+/// Merge tool presets: name, command template and whether the exit code is trusted
+///
+/// Two-way tools edit `{local}` in place, so `{merged}` is the same file.
+const PRESETS: &[(&str, &str, bool)] = &[
+    ("nvim", "nvim -d {remote} {local}", false),
+    ("vimdiff", "vimdiff {remote} {local}", false),
+    ("meld", "meld {remote} {local}", false),
+    ("kdiff3", "kdiff3 {local} {remote} -o {merged}", true),
+    (
+        "emacs",
+        "emacs --eval '(ediff-merge-files \"{local}\" \"{remote}\" nil \"{merged}\")'",
+        false,
+    ),
+    ("code", "code --wait --diff {remote} {local}", false),
+];
+
+/// Result of running a merge tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// The conflict was merged into the local file
+    Resolved,
+    /// The tool was closed without resolving the conflict
+    Aborted,
+}
+
+/// Files taking part in a merge
+pub struct MergeFiles<'a> {
+    /// Our version of the file
+    pub local: &'a str,
+    /// The other version of the file, e.g. a conflict copy
+    pub remote: &'a str,
+    /// Common ancestor of both versions, if known
+    pub base: Option<&'a str>,
+    /// File the merge result is written to
+    pub merged: &'a str,
+}
+
+/// External merge tool, run from a command template
+pub struct MergeTool {
+    template: String,
+    trust_exit_code: bool,
+}
+
+impl MergeTool {
+    /// Create a merge tool from a command template
+    ///
+    /// The template may use the placeholders `{local}`, `{remote}`, `{base}` and `{merged}`.
+    pub fn new(template: &str, trust_exit_code: bool) -> Self {
+        MergeTool {
+            template: template.to_string(),
+            trust_exit_code,
+        }
+    }
+
+    /// Look up a preset merge tool by name
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(preset, _, _)| *preset == name)
+            .map(|(_, template, trust)| Self::new(template, *trust))
+    }
+
+    /// Names of all preset merge tools
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _, _)| *name)
+    }
+
+    /// Create the merge tool configured by `merge_tool` or `merge_command`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut tool = match &config.merge_command {
+            Some(command) => Self::new(command, false),
+            None => match Self::preset(&config.merge_tool) {
+                Some(tool) => tool,
+                None => return Err(Error::UnknownMergeTool(config.merge_tool.clone())),
+            },
+        };
+        if let Some(trust) = config.merge_trust_exit_code {
+            tool.trust_exit_code = trust;
+        }
+        Ok(tool)
+    }
+
+    /// Name of the program the template runs
+    pub fn program(&self) -> Option<String> {
+        split_command(&self.template).ok()?.into_iter().next()
+    }
+
+    /// Build the command line with all placeholders replaced
+    pub fn command_line(&self, files: &MergeFiles, base: &str) -> Result<Vec<String>> {
+        let words = split_command(&self.template)?;
+        if words.is_empty() {
+            return Err(Error::InvalidConfigValue {
+                key: "merge_command".to_string(),
+                value: self.template.clone(),
+                expected: "expected a program and its arguments".to_string(),
+            });
+        }
+        Ok(words
+            .into_iter()
+            .map(|word| {
+                word.replace("{local}", files.local)
+                    .replace("{remote}", files.remote)
+                    .replace("{base}", base)
+                    .replace("{merged}", files.merged)
+            })
+            .collect())
+    }
+
+    /// Run the merge tool and find out whether the conflict was resolved
+    ///
+    /// Tools with a trusted exit code report a resolved merge by exiting successfully.
+    /// Other tools writing `{merged}` resolve by changing it, all remaining ones once local
+    /// and remote are identical.
+    pub fn merge(&self, files: &MergeFiles) -> Result<MergeOutcome> {
+        // like git mergetool, tools without a common ancestor get an empty base file
+        let empty_base = match files.base {
+            None if self.template.contains("{base}") => Some(EmptyFile::create()?),
+            _ => None,
+        };
+        let base = match (files.base, &empty_base) {
+            (Some(base), _) => base.to_string(),
+            (None, Some(empty)) => empty.path.to_string_lossy().to_string(),
+            (None, None) => String::new(),
+        };
+
+        // like git mergetool, tools writing a separate result resolve by changing it
+        let writes_merged = self.template.contains("{merged}");
+        let before = writes_merged.then(|| file_state(files.merged));
+
+        let command_line = self.command_line(files, &base)?;
+        let status = Command::new(&command_line[0])
+            .args(&command_line[1..])
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
-            .spawn()
-            .context("Failed to start neovim")?
-            .wait()
-            .context("neovim exited with error")?;
-        Ok(())
+            .status()
+            .map_err(|e| Error::spawn(&command_line[0], e))?;
+
+        if !status.success() {
+            return Ok(MergeOutcome::Aborted);
+        }
+        if self.trust_exit_code {
+            return Ok(MergeOutcome::Resolved);
+        }
+        if let Some(before) = before {
+            return Ok(if file_state(files.merged) != before {
+                MergeOutcome::Resolved
+            } else {
+                MergeOutcome::Aborted
+            });
+        }
+
+        let identical = match (std::fs::read(files.merged), std::fs::read(files.remote)) {
+            (Ok(merged), Ok(remote)) => merged == remote,
+            _ => false,
+        };
+        Ok(if identical {
+            MergeOutcome::Resolved
+        } else {
+            MergeOutcome::Aborted
+        })
+    }
+}
+
+/// Content and modification time of a file, `None` if it cannot be read
+fn file_state(path: &str) -> Option<(Vec<u8>, Option<SystemTime>)> {
+    let content = std::fs::read(path).ok()?;
+    let modified = std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok();
+    Some((content, modified))
+}
+
+/// Maximum number of line pairs compared by [`line_diff`]
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// A line of a line based diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// Line present in both files
+    Same(String),
+    /// Line only present in the first file
+    Removed(String),
+    /// Line only present in the second file
+    Added(String),
+}
+
+impl std::fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffLine::Same(line) => write!(f, " {}", line),
+            DiffLine::Removed(line) => write!(f, "-{}", line),
+            DiffLine::Added(line) => write!(f, "+{}", line),
+        }
+    }
+}
+
+/// Compute a line based diff between two texts
+///
+/// Returns `None` if the texts are too large to compare.
+pub fn line_diff(a: &str, b: &str) -> Option<Vec<DiffLine>> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (middle_a, middle_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if (middle_a.len() + 1) * (middle_b.len() + 1) > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // longest common subsequence table of the differing middle part
+    let width = middle_b.len() + 1;
+    let mut lcs = vec![0usize; (middle_a.len() + 1) * width];
+    for i in (0..middle_a.len()).rev() {
+        for j in (0..middle_b.len()).rev() {
+            lcs[i * width + j] = if middle_a[i] == middle_b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut diff: Vec<DiffLine> = a[..prefix]
+        .iter()
+        .map(|line| DiffLine::Same(line.to_string()))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < middle_a.len() || j < middle_b.len() {
+        if i < middle_a.len() && j < middle_b.len() && middle_a[i] == middle_b[j] {
+            diff.push(DiffLine::Same(middle_a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < middle_a.len()
+            && (j == middle_b.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            diff.push(DiffLine::Removed(middle_a[i].to_string()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(middle_b[j].to_string()));
+            j += 1;
+        }
+    }
+    diff.extend(
+        a[a.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::Same(line.to_string())),
+    );
+    Some(diff)
+}
+
+/// Diff two files, failing if they cannot be read or are too large
+pub fn diff_files(a: &str, b: &str) -> Result<Vec<DiffLine>> {
+    let read = |file: &str| {
+        std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file))
+    };
+    line_diff(&read(a)?, &read(b)?).context("Files are too large to diff")
+}
+
+/// Empty temporary file, removed when dropped
+struct EmptyFile {
+    path: PathBuf,
+}
+
+impl EmptyFile {
+    fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("resolvething-base-{}", std::process::id()));
+        std::fs::write(&path, "").context("Failed to create empty base file")?;
+        Ok(EmptyFile { path })
+    }
+}
+
+impl Drop for EmptyFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Split a command template into words, honoring single and double quotes
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated single quote in command: {}", command),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => bail!("Trailing backslash in command: {}", command),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated double quote in command: {}", command),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => word.push(c),
+                    None => bail!("Trailing backslash in command: {}", command),
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command("code --wait  --diff {remote} {local}").unwrap(),
+            vec!["code", "--wait", "--diff", "{remote}", "{local}"]
+        );
+        assert_eq!(
+            split_command(r#"emacs --eval '(ediff "{local}")' "a b" c\ d"#).unwrap(),
+            vec!["emacs", "--eval", r#"(ediff "{local}")"#, "a b", "c d"]
+        );
+        assert_eq!(split_command("''").unwrap(), vec![""]);
+        assert!(split_command("meld 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_command_line_placeholders() {
+        let tool = MergeTool::preset("kdiff3").unwrap();
+        let files = MergeFiles {
+            local: "notes.md",
+            remote: "notes.sync-conflict-20250101-120000-ABCDEFG.md",
+            base: None,
+            merged: "notes.md",
+        };
+        assert_eq!(
+            tool.command_line(&files, "").unwrap(),
+            vec![
+                "kdiff3",
+                "notes.md",
+                "notes.sync-conflict-20250101-120000-ABCDEFG.md",
+                "-o",
+                "notes.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        let mut config = Config::default();
+        assert_eq!(
+            MergeTool::from_config(&config)
+                .unwrap()
+                .program()
+                .as_deref(),
+            Some("nvim")
+        );
+
+        config.merge_tool = "nonexistent".to_string();
+        assert!(matches!(
+            MergeTool::from_config(&config),
+            Err(Error::UnknownMergeTool(name)) if name == "nonexistent"
+        ));
+
+        config.merge_command = Some("mymerge {local} {remote}".to_string());
+        config.merge_trust_exit_code = Some(true);
+        let tool = MergeTool::from_config(&config).unwrap();
+        assert_eq!(tool.program().as_deref(), Some("mymerge"));
+        assert!(tool.trust_exit_code);
+    }
+
+    #[test]
+    fn test_line_diff() {
+        let diff = line_diff("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n").unwrap();
+        let rendered: Vec<String> = diff.iter().map(|line| line.to_string()).collect();
+        assert_eq!(rendered, vec![" a", "-b", "+x", " c", " d", "+e"]);
+
+        assert!(
+            line_diff("same\n", "same\n")
+                .unwrap()
+                .iter()
+                .all(|line| matches!(line, DiffLine::Same(_)))
+        );
+        assert_eq!(line_diff("", "").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_merge_outcome_from_exit_status() {
+        let files = MergeFiles {
+            local: "/nonexistent/local",
+            remote: "/nonexistent/remote",
+            base: None,
+            merged: "/nonexistent/local",
+        };
+        assert_eq!(
+            MergeTool::new("true", true).merge(&files).unwrap(),
+            MergeOutcome::Resolved
+        );
+        assert_eq!(
+            MergeTool::new("false", true).merge(&files).unwrap(),
+            MergeOutcome::Aborted
+        );
+        // untrusted tools need identical files
+        assert_eq!(
+            MergeTool::new("true", false).merge(&files).unwrap(),
+            MergeOutcome::Aborted
+        );
+    }
+
+    #[test]
+    fn test_merge_outcome_from_merged_file() {
+        let dir = TempDir::new("merge");
+        let local = dir.join("local.md").to_string_lossy().to_string();
+        let remote = dir.join("remote.md").to_string_lossy().to_string();
+        std::fs::write(&local, "a\n").unwrap();
+        std::fs::write(&remote, "b\n").unwrap();
+        let files = MergeFiles {
+            local: &local,
+            remote: &remote,
+            base: None,
+            merged: &local,
+        };
+
+        // untrusted tools writing {merged} resolve by changing it
+        assert_eq!(
+            MergeTool::new("sh -c 'echo merged > \"$1\"' sh {merged}", false)
+                .merge(&files)
+                .unwrap(),
+            MergeOutcome::Resolved
+        );
+        assert_eq!(std::fs::read_to_string(&local).unwrap(), "merged\n");
+        assert_eq!(
+            MergeTool::new("sh -c true sh {merged}", false)
+                .merge(&files)
+                .unwrap(),
+            MergeOutcome::Aborted
+        );
     }
 }
@@ -1,11 +1,19 @@
-use std::process::Command;
+use std::collections::HashMap;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use std::str;
 
-use crate::config::Config;
-use crate::fzf::Fzf;
-use crate::sync_conflict_regex;
+use crate::config::{Config, Profile};
+use crate::scan::FolderScan;
+use crate::selector::Selector;
 use crate::trash::Trash;
-use anyhow::{Context, Result};
+use crate::tui::{Action, Progress};
+use crate::{Context, Error, Result};
+use crate::{rename_file, sync_conflict_info_regex, sync_conflict_regex};
+use serde::Serialize;
+use serde::ser::{SerializeStruct, Serializer};
 
 /// Runner for the fclones tool to find duplicate files
 pub struct FclonesRunner {
@@ -13,9 +21,10 @@ pub struct FclonesRunner {
 }
 
 /// Represents a file in the Syncthing synchronization context
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct SyncThingFile {
     pub path: String,
+    #[serde(rename = "type")]
     pub file_type: SyncThingFileType,
 }
 
@@ -40,21 +49,44 @@ impl SyncThingFile {
             file_type: filetype,
         }
     }
+
+    /// Short ID of the device that created this conflict copy, if it is one
+    pub fn device(&self) -> Option<String> {
+        sync_conflict_info_regex()
+            .captures(&self.path)
+            .map(|captures| captures[3].to_string())
+    }
 }
 
 /// Types of files in the Syncthing synchronization workflow
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub enum SyncThingFileType {
     /// Regular file without special suffixes
+    #[serde(rename = "regular")]
     Regular,
     /// Syncthing conflict file (*.sync-conflict-*)
+    #[serde(rename = "conflict")]
     StConflict,
     /// Original file backup (*.orig)
+    #[serde(rename = "orig")]
     OrigFile,
     /// Temporary file (*.tmp)
+    #[serde(rename = "tmp")]
     TmpFile,
 }
 
+impl SyncThingFileType {
+    /// Short human readable name of the file type
+    pub fn label(&self) -> &'static str {
+        match self {
+            SyncThingFileType::Regular => "regular",
+            SyncThingFileType::StConflict => "conflict",
+            SyncThingFileType::OrigFile => "orig",
+            SyncThingFileType::TmpFile => "tmp",
+        }
+    }
+}
+
 impl Default for FclonesRunner {
     fn default() -> Self {
         Self::new()
@@ -68,22 +100,63 @@ impl FclonesRunner {
         }
     }
 
-    pub fn run_recursively(&mut self, directory: &str) -> Result<()> {
-        let output = Command::new("fclones")
+    /// Find duplicates in a folder
+    pub fn for_profile(profile: &Profile) -> Result<Self> {
+        Self::from_scan(&FolderScan::new(profile)?)
+    }
+
+    /// Find duplicates among the files of a scan
+    ///
+    /// Only files sharing their size with another file are handed to fclones.
+    pub fn from_scan(scan: &FolderScan) -> Result<Self> {
+        let mut runner = FclonesRunner::new();
+        let candidates = scan.duplicate_candidates();
+        if !candidates.is_empty() {
+            runner.run_on(&candidates)?;
+        }
+        let sizes: HashMap<&Path, u64> = scan
+            .files
+            .iter()
+            .map(|file| (file.path.as_path(), file.size))
+            .collect();
+        for group in &mut runner.duplicate_groups {
+            group.size = group
+                .files
+                .iter()
+                .find_map(|file| sizes.get(Path::new(&file.path)).copied())
+                .unwrap_or(0);
+        }
+        Ok(runner)
+    }
+
+    /// Find duplicates among the given files
+    pub fn run_on(&mut self, files: &[&Path]) -> Result<()> {
+        let mut child = Command::new("fclones")
             .arg("group")
+            .arg("--stdin")
             .arg("--hidden")
-            .arg(directory)
             .arg("--format")
             .arg("fdupes")
             .arg("--cache")
-            .arg("--exclude")
-            .arg("**/.stversions/**")
-            .output()
-            .context("Failed to execute fclones")?;
-
-        if !output.status.success() {
-            anyhow::bail!("fclones failed with status: {}", output.status);
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::spawn("fclones", e))?;
+        let mut stdin = child.stdin.take().context("Failed to open fclones stdin")?;
+        let mut input = Vec::new();
+        for file in files {
+            input.extend_from_slice(file.as_os_str().as_bytes());
+            input.push(b'\n');
         }
+        // write from another thread, fclones may fill its output pipes before reading all paths
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child
+            .wait_with_output()
+            .context("Failed to read fclones output")?;
+        let written = writer.join().expect("writing fclones input does not panic");
+        let output = Error::check_output("fclones", output)?;
+        written.context("Failed to write fclones input")?;
 
         let stdout =
             str::from_utf8(&output.stdout).context("Failed to parse fclones output as UTF-8")?;
@@ -115,39 +188,57 @@ impl FclonesRunner {
 /// Represents a group of duplicate files
 pub struct Duplicate {
     pub files: Vec<SyncThingFile>,
+    /// Size of each file in bytes, 0 if unknown
+    pub size: u64,
+}
+
+impl Serialize for Duplicate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Duplicate", 3)?;
+        state.serialize_field("files", &self.files)?;
+        state.serialize_field("size", &self.size)?;
+        state.serialize_field("reclaimable", &self.reclaimable())?;
+        state.end()
+    }
 }
 
 impl Duplicate {
+    /// A group of the given files, the size is set by [`FclonesRunner::from_scan`]
     pub fn new(file_paths: Vec<String>) -> Self {
         let files = file_paths.into_iter().map(SyncThingFile::new).collect();
-        Duplicate { files }
+        Duplicate { files, size: 0 }
+    }
+
+    /// Bytes freed by keeping a single file of the group
+    pub fn reclaimable(&self) -> u64 {
+        self.size * self.files.len().saturating_sub(1) as u64
     }
 
-    pub fn choose(&self) -> Option<String> {
+    /// Select the file to keep, either automatically or with the given selector
+    pub fn choose(&self, selector: &dyn Selector, progress: Progress) -> Result<Action> {
         // Check if we can automatically select a file
         if let Some(auto_selected) = self.try_auto_select() {
-            println!("Auto-selected file: {}", auto_selected);
-            return Some(auto_selected);
+            eprintln!("Auto-selected file: {}", auto_selected);
+            let index = self.files.iter().position(|f| f.path == auto_selected);
+            return Ok(index.map_or(Action::Skip, Action::Keep));
         }
 
         // Otherwise, proceed with normal selection
-        let options: Vec<String> = self.files.iter().map(|file| file.path.clone()).collect();
+        let options = self.paths();
 
-        let choice = Fzf::select(options);
-        if let Some(selected) = choice {
-            println!("Selected: {}", selected);
-            Some(selected)
-        } else {
-            println!("No selection made");
-            None
+        let action = selector.choose("Duplicates", &options, progress, false)?;
+        match action {
+            Action::Keep(index) => eprintln!("Selected: {}", options[index]),
+            _ => eprintln!("No selection made"),
         }
+        Ok(action)
     }
 
     /// Attempts to automatically select a file based on file types
     ///
     /// Returns the path of the selected file if auto-selection is possible,
     /// or None if user selection is needed
-    fn try_auto_select(&self) -> Option<String> {
+    pub fn try_auto_select(&self) -> Option<String> {
         // Count files by type
 
         let mut regular_files = vec![];
@@ -186,6 +277,11 @@ impl Duplicate {
         None
     }
 
+    /// Paths of all files in the group
+    pub fn paths(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.path.clone()).collect()
+    }
+
     /// Keeps the specified file and moves other duplicates to the trash.
     ///
     /// # Arguments
@@ -205,13 +301,51 @@ impl Duplicate {
     /// let config = Config::default();
     /// let _ = duplicate.keep_only("/path/to/file1.txt".to_string(), &config);
     /// ```
-    pub fn keep_only(&self, keep: String, config: &Config) -> Result<()> {
+    pub fn keep_only(&self, keep: String, config: &Config) -> Result<Vec<String>> {
+        self.keep_several(&[keep], config)
+    }
+
+    /// Keeps the specified files and moves all other duplicates to the trash.
+    ///
+    /// Returns the trashed files.
+    pub fn keep_several(&self, keep: &[String], config: &Config) -> Result<Vec<String>> {
+        let mut trashed = Vec::new();
         for file in &self.files {
-            if file.path != keep {
+            if !keep.contains(&file.path) {
                 Trash::trash(&file.path, config)?;
+                trashed.push(file.path.clone());
             }
         }
-        Ok(())
+        Ok(trashed)
+    }
+
+    /// Apply a decision made in a selector
+    ///
+    /// Returns a description of what was done and the trashed files, or `None` if the group
+    /// is left as it is.
+    pub fn apply(&self, action: &Action, config: &Config) -> Result<Option<(String, Vec<String>)>> {
+        let path = |index: usize| self.files[index].path.clone();
+        match action {
+            Action::Keep(index) => {
+                let trashed = self.keep_only(path(*index), config)?;
+                Ok(Some((format!("kept {}", path(*index)), trashed)))
+            }
+            Action::KeepSeveral(indices) => {
+                let keep: Vec<String> = indices.iter().map(|&index| path(index)).collect();
+                let trashed = self.keep_several(&keep, config)?;
+                Ok(Some((format!("kept {}", keep.join(", ")), trashed)))
+            }
+            Action::Rename(index, name) => {
+                let trashed = self.keep_only(path(*index), config)?;
+                let renamed = rename_file(&path(*index), name)?;
+                Ok(Some((
+                    format!("kept {} as {}", path(*index), renamed),
+                    trashed,
+                )))
+            }
+            Action::KeepAll => Ok(Some(("kept all".to_string(), Vec::new()))),
+            Action::Merge(_) | Action::History | Action::Skip | Action::Quit => Ok(None),
+        }
     }
 }
 
@@ -0,0 +1,257 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+
+/// Errors returned by the library
+///
+/// Errors may be wrapped in [`Error::Context`] describing what was being done, use
+/// [`Error::root`] to match on the underlying failure.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A required external command is not installed
+    #[error("{command} is not installed")]
+    MissingDependency { command: String },
+    /// A path is not valid UTF-8
+    #[error("invalid UTF-8 in path {}", path.display())]
+    InvalidPath { path: PathBuf },
+    /// An external command exited unsuccessfully
+    #[error(
+        "{tool} failed with {status}{}",
+        if stderr.is_empty() { String::new() } else { format!(": {}", stderr) }
+    )]
+    ToolFailed {
+        tool: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// A configuration file could not be parsed
+    #[error(
+        "{}{}: {message}",
+        path.display(),
+        location.map_or(String::new(), |(line, column)| format!(":{}:{}", line, column))
+    )]
+    ConfigParse {
+        path: PathBuf,
+        /// Line and column of the error, both starting at 1
+        location: Option<(usize, usize)>,
+        message: String,
+    },
+    /// A setting or option has a value that cannot be used, e.g. a malformed age or size
+    #[error("Invalid {key} '{value}', {expected}")]
+    InvalidConfigValue {
+        key: String,
+        value: String,
+        /// What a valid value looks like
+        expected: String,
+    },
+    /// `merge_tool` names none of the presets
+    #[error(
+        "Unknown merge tool '{0}', expected one of {presets:?} or a merge_command",
+        presets = crate::diff::MergeTool::preset_names().collect::<Vec<_>>()
+    )]
+    UnknownMergeTool(String),
+    /// A line of a `.stignore` file, or of a file it includes, is invalid
+    #[error("{}:{line}: {message}", path.display())]
+    Ignore {
+        path: PathBuf,
+        /// Line number, starting at 1
+        line: usize,
+        message: String,
+    },
+    /// The user cancelled the operation
+    #[error("aborted by user")]
+    Aborted,
+    /// A file or folder does not exist
+    #[error("{} not found", path.display())]
+    NotFound { path: PathBuf },
+    /// A file would have been overwritten
+    #[error("Refusing to overwrite existing file {}", path.display())]
+    FileExists { path: PathBuf },
+    /// No configured folder has the requested profile name
+    #[error("No folder with profile name '{name}' in config")]
+    UnknownProfile { name: String },
+    /// Syncthing is busy with the folder, e.g. scanning or syncing
+    #[error("Syncthing folder {folder} is {state}")]
+    FolderBusy { folder: String, state: String },
+    /// The git repository has staged changes that a commit would include
+    #[error("{} has staged changes, commit them or pass --allow-dirty", root.display())]
+    DirtyRepository { root: PathBuf },
+    /// There are no old versions of a file
+    #[error("No old versions of {} found", path.display())]
+    NoVersions { path: PathBuf },
+    /// Some files could not be moved to the trash, the others were
+    #[error("Failed to trash {failed} files")]
+    TrashFailed { failed: usize },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Utf8(#[from] std::str::Utf8Error),
+    #[error(transparent)]
+    Env(#[from] std::env::VarError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    TomlParse(#[from] toml::de::Error),
+    #[error(transparent)]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error(transparent)]
+    Xml(#[from] roxmltree::Error),
+    #[error(transparent)]
+    Walk(#[from] walkdir::Error),
+    #[error(transparent)]
+    Pattern(#[from] globset::Error),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Watch(#[from] notify::Error),
+    #[error(transparent)]
+    Http(#[from] Box<ureq::Error>),
+    /// Any other failure
+    #[error("{0}")]
+    Message(String),
+    /// A failure with a description of what was being done
+    #[error("{context}: {inner}")]
+    Context { context: String, inner: Box<Error> },
+}
+
+impl Error {
+    /// The underlying failure, without any context
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { inner, .. } => inner.root(),
+            error => error,
+        }
+    }
+
+    /// A command that could not be started, `MissingDependency` if it is not installed
+    pub fn spawn(command: &str, error: std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            return Error::MissingDependency {
+                command: command.to_string(),
+            };
+        }
+        Error::Context {
+            context: format!("Failed to run {}", command),
+            inner: Box::new(error.into()),
+        }
+    }
+
+    /// Fail with the output of an external command unless it succeeded
+    pub fn check_output(tool: &str, output: Output) -> Result<Output> {
+        if output.status.success() {
+            return Ok(output);
+        }
+        Err(Error::ToolFailed {
+            tool: tool.to_string(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+
+    /// A TOML config file that failed to parse, located by the span of the error
+    pub fn config_parse(path: &Path, content: &str, error: &toml::de::Error) -> Self {
+        let location = error.span().map(|span| {
+            let before = &content[..span.start.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            (line, column)
+        });
+        Error::ConfigParse {
+            path: path.to_path_buf(),
+            location,
+            message: error.message().to_string(),
+        }
+    }
+}
+
+impl From<ureq::Error> for Error {
+    fn from(error: ureq::Error) -> Self {
+        Error::Http(Box::new(error))
+    }
+}
+
+/// Result of the library, failing with [`Error`]
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Add a description of what was being done to an error or a missing value
+pub trait Context<T> {
+    fn context<C: Display>(self, context: C) -> Result<T>;
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.map_err(|error| Error::Context {
+            context: context.to_string(),
+            inner: Box::new(error.into()),
+        })
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|error| Error::Context {
+            context: context().to_string(),
+            inner: Box::new(error.into()),
+        })
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.ok_or_else(|| Error::Message(context.to_string()))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.ok_or_else(|| Error::Message(context().to_string()))
+    }
+}
+
+/// Return early with an [`Error::Message`]
+#[macro_export]
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::Error::Message(format!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root() {
+        let result: Result<()> = Err(Error::Aborted);
+        let error = result
+            .context("Failed to ask")
+            .context("Failed to run")
+            .unwrap_err();
+        assert!(matches!(error.root(), Error::Aborted));
+        assert_eq!(
+            error.to_string(),
+            "Failed to run: Failed to ask: aborted by user"
+        );
+    }
+
+    #[test]
+    fn test_spawn_missing_command() {
+        let error = std::process::Command::new("resolvething-no-such-command")
+            .output()
+            .map_err(|e| Error::spawn("resolvething-no-such-command", e))
+            .unwrap_err();
+        assert!(matches!(error, Error::MissingDependency { .. }));
+    }
+
+    #[test]
+    fn test_config_parse() {
+        let content = "discover = true\nmerge_tool = \n";
+        let error = toml::from_str::<toml::Table>(content).unwrap_err();
+        let error = Error::config_parse(Path::new("config.toml"), content, &error);
+        assert!(matches!(
+            error,
+            Error::ConfigParse {
+                location: Some((2, 14)),
+                ..
+            }
+        ));
+    }
+}
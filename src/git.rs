@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::batch::{ItemKind, SummaryItem};
+use crate::{Context, Error, Result};
+
+/// Message of the commit made before a folder is changed
+pub const SNAPSHOT_MESSAGE: &str = "resolvething: snapshot before resolving";
+
+/// A git repository containing a synced folder
+pub struct GitRepo {
+    root: PathBuf,
+}
+
+impl GitRepo {
+    /// The repository `directory` is in, `None` if it is not in one or git is missing
+    pub fn find(directory: &Path) -> Option<Self> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(["rev-parse", "--show-toplevel"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let root = String::from_utf8(output.stdout).ok()?;
+        Some(GitRepo {
+            root: PathBuf::from(root.trim_end()),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Check whether changes are staged for the next commit
+    pub fn has_staged_changes(&self) -> Result<bool> {
+        self.has_staged_changes_in(&[])
+    }
+
+    /// Check whether changes matching the pathspec are staged, anywhere if it is empty
+    fn has_staged_changes_in(&self, pathspec: &[&str]) -> Result<bool> {
+        let mut args = vec!["diff", "--cached", "--quiet", "--"];
+        args.extend(pathspec);
+        let output = self.git(&args)?;
+        // exits with 1 if there are differences
+        if output.status.code() == Some(1) {
+            return Ok(true);
+        }
+        self.check(output)?;
+        Ok(false)
+    }
+
+    /// Commit all changes to tracked files in `directory`, returns false if there was nothing
+    /// to commit
+    ///
+    /// Untracked files, e.g. old versions or conflict copies, are never added. Changes staged
+    /// outside of `directory` stay staged and are not committed.
+    pub fn commit_all(&self, directory: &Path, message: &str) -> Result<bool> {
+        let directory = directory.to_string_lossy();
+        self.check(self.git(&["add", "--update", "--", &directory])?)?;
+        if !self.has_staged_changes_in(&[&directory])? {
+            return Ok(false);
+        }
+        self.check(self.git(&[
+            "commit",
+            "--quiet",
+            "--message",
+            message,
+            "--only",
+            "--",
+            &directory,
+        ])?)?;
+        Ok(true)
+    }
+
+    fn git(&self, args: &[&str]) -> Result<Output> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .args(args)
+            .output()
+            .map_err(|e| Error::spawn("git", e))
+    }
+
+    fn check(&self, output: Output) -> Result<()> {
+        Error::check_output("git", output)
+            .with_context(|| format!("In repository {}", self.root.display()))?;
+        Ok(())
+    }
+}
+
+/// Message of the commit made after resolving the given items
+///
+/// Lists every resolved conflict and every removed duplicate, paths relative to `directory`.
+pub fn commit_message(directory: &Path, items: &[SummaryItem]) -> String {
+    let relative = |file: &str| {
+        Path::new(file)
+            .strip_prefix(directory)
+            .map_or_else(|_| file.to_string(), |path| path.display().to_string())
+    };
+    let mut message = format!("resolvething: resolve {} items\n", items.len());
+    for item in items {
+        match item.kind {
+            ItemKind::Conflict => message.push_str(&format!(
+                "\n- conflict {}: {}",
+                item.files
+                    .iter()
+                    .map(|file| relative(file))
+                    .collect::<Vec<_>>()
+                    .join(" <- "),
+                item.action
+            )),
+            ItemKind::Duplicate => {
+                for file in &item.trashed {
+                    message.push_str(&format!(
+                        "\n- removed duplicate {}: {}",
+                        relative(file),
+                        item.action
+                    ));
+                }
+            }
+        }
+    }
+    message.push('\n');
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn item(kind: ItemKind, files: &[&str], action: &str, trashed: &[&str]) -> SummaryItem {
+        let strings = |paths: &[&str]| paths.iter().map(|path| path.to_string()).collect();
+        SummaryItem {
+            folder: "wiki".to_string(),
+            kind,
+            files: strings(files),
+            action: action.to_string(),
+            trashed: strings(trashed),
+        }
+    }
+
+    #[test]
+    fn test_commit_all() {
+        let dir = TempDir::new("git");
+        std::fs::create_dir_all(dir.join("wiki")).unwrap();
+        let git = |args: &[&str]| Command::new("git").arg("-C").arg(&*dir).args(args).output();
+        if git(&["init", "--quiet"]).is_err() {
+            return;
+        }
+        git(&["config", "user.name", "test"]).unwrap();
+        git(&["config", "user.email", "test@example.com"]).unwrap();
+        std::fs::write(dir.join("wiki/a.md"), "a").unwrap();
+        std::fs::write(dir.join("other.md"), "b").unwrap();
+
+        let repo = GitRepo::find(&dir.join("wiki")).unwrap();
+        assert!(!repo.has_staged_changes().unwrap());
+        assert!(
+            !repo
+                .commit_all(&dir.join("wiki"), SNAPSHOT_MESSAGE)
+                .unwrap()
+        );
+        git(&["add", "wiki/a.md"]).unwrap();
+        assert!(
+            repo.commit_all(&dir.join("wiki"), SNAPSHOT_MESSAGE)
+                .unwrap()
+        );
+        assert!(
+            !repo
+                .commit_all(&dir.join("wiki"), SNAPSHOT_MESSAGE)
+                .unwrap()
+        );
+        git(&["add", "other.md"]).unwrap();
+        assert!(repo.has_staged_changes().unwrap());
+
+        // with --allow-dirty, staged work elsewhere must stay out of the commit
+        std::fs::write(dir.join("wiki/a.md"), "changed").unwrap();
+        // as must untracked old versions and conflict copies
+        std::fs::create_dir_all(dir.join("wiki/.stversions")).unwrap();
+        std::fs::write(dir.join("wiki/.stversions/a~20240101-120000.md"), "a").unwrap();
+        std::fs::write(dir.join("wiki/a.sync-conflict-20240101-120000-ABC.md"), "b").unwrap();
+        assert!(repo.commit_all(&dir.join("wiki"), "resolve").unwrap());
+        let committed = git(&["show", "--name-only", "--format=", "HEAD"]).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&committed.stdout).trim(),
+            "wiki/a.md"
+        );
+        let staged = git(&["diff", "--cached", "--name-only"]).unwrap();
+        assert_eq!(String::from_utf8_lossy(&staged.stdout).trim(), "other.md");
+        let tracked = git(&["ls-files", "wiki"]).unwrap();
+        assert_eq!(String::from_utf8_lossy(&tracked.stdout).trim(), "wiki/a.md");
+    }
+
+    #[test]
+    fn test_commit_message() {
+        let items = [
+            item(
+                ItemKind::Conflict,
+                &["/wiki/a.md", "/wiki/a.sync-conflict-20240101-120000-ABC.md"],
+                "merged",
+                &[],
+            ),
+            item(
+                ItemKind::Duplicate,
+                &["/wiki/c.md", "/wiki/b.md", "/elsewhere/b.md"],
+                "kept /wiki/c.md",
+                &["/wiki/b.md", "/elsewhere/b.md"],
+            ),
+        ];
+        assert_eq!(
+            commit_message(Path::new("/wiki"), &items),
+            "resolvething: resolve 2 items\n\n\
+             - conflict a.md <- a.sync-conflict-20240101-120000-ABC.md: merged\n\
+             - removed duplicate b.md: kept /wiki/c.md\n\
+             - removed duplicate /elsewhere/b.md: kept /wiki/c.md\n"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Points of a run at which a hook command can be run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// Before a folder is scanned, a failing hook skips the folder
+    PreScan,
+    /// For every conflict found
+    OnConflictFound,
+    /// Before a conflict or duplicate group is resolved, a failing hook skips it
+    PreResolve,
+    /// After a conflict or duplicate group was resolved
+    PostResolve,
+    /// For every file trashed while resolving a duplicate group
+    OnDuplicateTrashed,
+    /// After the run with its summary
+    PostRun,
+}
+
+impl Hook {
+    /// Name of the hook in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::PreScan => "pre_scan",
+            Hook::OnConflictFound => "on_conflict_found",
+            Hook::PreResolve => "pre_resolve",
+            Hook::PostResolve => "post_resolve",
+            Hook::OnDuplicateTrashed => "on_duplicate_trashed",
+            Hook::PostRun => "post_run",
+        }
+    }
+}
+
+/// Shell commands run at points of a run
+///
+/// Each command gets a JSON description of the item on stdin and the variables
+/// `RESOLVETHING_HOOK`, `RESOLVETHING_FOLDER`, `RESOLVETHING_FOLDER_NAME`,
+/// `RESOLVETHING_FILES` (one per line) and `RESOLVETHING_ACTION` where they apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_scan: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_conflict_found: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_resolve: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_resolve: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_duplicate_trashed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_run: Option<String>,
+}
+
+/// What a hook is run for, passed as environment variables and JSON
+#[derive(Debug, Default, Serialize)]
+pub struct HookContext<'a> {
+    /// Profile name of the folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<&'a Path>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub files: &'a [String],
+    /// What was done to the item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    hook: &'static str,
+    #[serde(flatten)]
+    context: &'a HookContext<'a>,
+    /// The conflict, duplicate group or summary involved
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    item: serde_json::Value,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Hooks::default()
+    }
+
+    /// Command configured for a hook
+    pub fn command(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::PreScan => &self.pre_scan,
+            Hook::OnConflictFound => &self.on_conflict_found,
+            Hook::PreResolve => &self.pre_resolve,
+            Hook::PostResolve => &self.post_resolve,
+            Hook::OnDuplicateTrashed => &self.on_duplicate_trashed,
+            Hook::PostRun => &self.post_run,
+        }
+        .as_deref()
+    }
+
+    /// Run the command of a hook, if configured, and fail if it exits unsuccessfully
+    pub fn run(&self, hook: Hook, context: &HookContext, item: &impl Serialize) -> Result<()> {
+        let Some(command) = self.command(hook) else {
+            return Ok(());
+        };
+        let payload = serde_json::to_vec(&Payload {
+            hook: hook.name(),
+            context,
+            item: serde_json::to_value(item)?,
+        })?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(environment(hook, context))
+            .stdin(Stdio::piped())
+            // stdout is reserved for the output of resolvething
+            .stdout(std::io::stderr())
+            .spawn()
+            .with_context(|| format!("Failed to run {} hook", hook.name()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            match stdin.write_all(&payload) {
+                // hooks do not have to read their input
+                Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+                    return Err(e).context("Failed to write hook input");
+                }
+                _ => {}
+            }
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            // stderr of the hook goes to the terminal
+            return Err(Error::ToolFailed {
+                tool: format!("{} hook", hook.name()),
+                status,
+                stderr: String::new(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Run the command of a hook, printing a warning if it fails
+    pub fn notify(&self, hook: Hook, context: &HookContext, item: &impl Serialize) {
+        if let Err(e) = self.run(hook, context, item) {
+            eprintln!("warning: {:#}", e);
+        }
+    }
+}
+
+fn environment(hook: Hook, context: &HookContext) -> Vec<(&'static str, String)> {
+    let mut vars = vec![("RESOLVETHING_HOOK", hook.name().to_string())];
+    if let Some(directory) = context.directory {
+        vars.push(("RESOLVETHING_FOLDER", directory.display().to_string()));
+    }
+    if let Some(folder) = context.folder {
+        vars.push(("RESOLVETHING_FOLDER_NAME", folder.to_string()));
+    }
+    if !context.files.is_empty() {
+        vars.push(("RESOLVETHING_FILES", context.files.join("\n")));
+    }
+    if let Some(action) = context.action {
+        vars.push(("RESOLVETHING_ACTION", action.to_string()));
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_run_hook() {
+        let dir = TempDir::new("hook");
+        let out = dir.join("out");
+        let hooks = Hooks {
+            post_resolve: Some(format!(
+                "{{ echo \"$RESOLVETHING_HOOK $RESOLVETHING_ACTION\"; cat; }} > {}",
+                out.display()
+            )),
+            pre_resolve: Some("exit 1".to_string()),
+            ..Default::default()
+        };
+        let files = ["a.md".to_string()];
+        let context = HookContext {
+            folder: Some("wiki"),
+            files: &files,
+            action: Some("merged"),
+            ..Default::default()
+        };
+        hooks
+            .run(Hook::PostResolve, &context, &serde_json::json!({"size": 3}))
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "post_resolve merged\n{\"hook\":\"post_resolve\",\"folder\":\"wiki\",\"files\":[\"a.md\"],\"action\":\"merged\",\"item\":{\"size\":3}}"
+        );
+        assert!(hooks.run(Hook::PreResolve, &context, &()).is_err());
+        assert!(hooks.run(Hook::PreScan, &context, &()).is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, FolderConfig, expand_path};
+use crate::diff::MergeTool;
+use crate::selector::{Selector, SelectorKind};
+use crate::syncthing;
+use crate::{Context, Error, Result};
+
+/// Trash commands offered by the wizard, in order of preference
+const TRASH_COMMANDS: &[&str] = &["trash", "trash-put", "gtrash", "rmtrash"];
+
+/// Helper tools reported by the wizard and what they are used for
+const HELPER_TOOLS: &[(&str, &str)] = &[
+    ("fclones", "finding duplicates, required"),
+    ("fzf", "fzf selector"),
+    ("sk", "skim selector"),
+    ("rofi", "rofi selector"),
+    ("dmenu", "dmenu selector"),
+    ("bat", "file previews in fzf and skim"),
+];
+
+/// Answers of the Syncthing folder question
+const DISCOVER_ENTRY: &str = "scan all Syncthing folders, including ones added later";
+const CHOOSE_FOLDERS_ENTRY: &str = "choose Syncthing folders";
+const ENTER_FOLDER_ENTRY: &str = "enter a folder";
+
+/// Answers of the review question
+const SAVE_ENTRY: &str = "save";
+const REVIEW_ENTRY: &str = "review";
+const ABORT_ENTRY: &str = "abort";
+
+/// Interactive setup of a new config file
+pub struct Wizard<'a> {
+    selector: &'a dyn Selector,
+    config: Config,
+    /// Syncthing's config.xml to offer folders from
+    syncthing_config: Option<PathBuf>,
+}
+
+impl<'a> Wizard<'a> {
+    pub fn new(selector: &'a dyn Selector) -> Self {
+        Wizard {
+            selector,
+            config: Config::default(),
+            syncthing_config: syncthing::find_config(),
+        }
+    }
+
+    /// Offer the folders of another Syncthing config.xml, none if `None`
+    pub fn with_syncthing_config(mut self, path: Option<PathBuf>) -> Self {
+        self.syncthing_config = path;
+        self
+    }
+
+    /// Ask all questions and write the config to `path`
+    ///
+    /// Fails with [`Error::Aborted`] if the user aborted without saving.
+    pub fn run(mut self, path: &Path) -> Result<()> {
+        if path.exists() && !self.confirm(&format!("overwrite {}?", path.display()))? {
+            return Err(Error::Aborted);
+        }
+        self.report_tools();
+        self.ask_folders()?;
+        self.ask_merge_tool()?;
+        self.ask_selector()?;
+        self.ask_trash_command()?;
+
+        let items = [SAVE_ENTRY, REVIEW_ENTRY, ABORT_ENTRY].map(String::from);
+        loop {
+            let prompt = format!("write {}", path.display());
+            match self.selector.select(&prompt, &items)? {
+                Some(0) => break,
+                Some(1) => eprintln!("\n{}", self.config.to_commented_toml()?),
+                _ => return Err(Error::Aborted),
+            }
+        }
+        self.config.save_to(path)?;
+        eprintln!("wrote {}", path.display());
+        Ok(())
+    }
+
+    fn confirm(&self, prompt: &str) -> Result<bool> {
+        let items = ["yes", "no"].map(String::from);
+        Ok(self.selector.select(prompt, &items)? == Some(0))
+    }
+
+    fn report_tools(&self) {
+        for (tool, purpose) in HELPER_TOOLS {
+            let status = if installed(tool) { "found" } else { "missing" };
+            eprintln!("{:>8} {:<8} {}", status, tool, purpose);
+        }
+    }
+
+    fn ask_folders(&mut self) -> Result<()> {
+        let found = self
+            .syncthing_config
+            .as_ref()
+            .map(|path| syncthing::load(path))
+            .transpose()
+            .context("Failed to read Syncthing's config")?
+            .unwrap_or_default();
+        let found: Vec<_> = found.into_iter().filter(|folder| !folder.paused).collect();
+
+        if !found.is_empty() {
+            eprintln!("found {} Syncthing folders", found.len());
+            let items =
+                [DISCOVER_ENTRY, CHOOSE_FOLDERS_ENTRY, ENTER_FOLDER_ENTRY].map(String::from);
+            match self.selector.select("folders to scan", &items)? {
+                Some(0) => {
+                    self.config.discover = true;
+                    return Ok(());
+                }
+                Some(1) => {
+                    let names: Vec<String> = found
+                        .iter()
+                        .map(|folder| format!("{} ({})", folder.name(), folder.path.display()))
+                        .collect();
+                    for index in self.selector.select_several(&names)? {
+                        let folder = &found[index];
+                        let versions_path = folder.versioning.as_ref().and_then(|v| v.path.clone());
+                        self.config.folders.push(FolderConfig {
+                            name: Some(folder.name().to_string()),
+                            path: folder.path.clone(),
+                            read_only: folder.is_read_only().then_some(true),
+                            versions_path,
+                            ..Default::default()
+                        });
+                    }
+                    if !self.config.folders.is_empty() {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let prompt = format!(
+            "folder to scan [{}]",
+            self.config.working_directory.display()
+        );
+        if let Some(folder) = self.selector.input(&prompt)? {
+            self.config.working_directory = expand_path(&PathBuf::from(folder))?;
+        }
+        if !self.config.working_directory.is_dir() {
+            eprintln!(
+                "warning: {} does not exist",
+                self.config.working_directory.display()
+            );
+        }
+        Ok(())
+    }
+
+    fn ask_merge_tool(&mut self) -> Result<()> {
+        let names: Vec<&str> = MergeTool::preset_names().collect();
+        let items: Vec<String> = names
+            .iter()
+            .map(|name| {
+                let program = MergeTool::preset(name).and_then(|tool| tool.program());
+                match program {
+                    Some(program) if installed(&program) => name.to_string(),
+                    _ => format!("{} (not installed)", name),
+                }
+            })
+            .collect();
+        if let Some(index) = self.selector.select("merge tool", &items)? {
+            self.config.merge_tool = names[index].to_string();
+        }
+        Ok(())
+    }
+
+    fn ask_selector(&mut self) -> Result<()> {
+        let items: Vec<String> = SelectorKind::ALL
+            .iter()
+            .map(|kind| match kind.command() {
+                Some(command) if !installed(command) => {
+                    format!("{} (not installed)", kind.name())
+                }
+                _ => kind.name(),
+            })
+            .collect();
+        if let Some(index) = self.selector.select("selector", &items)? {
+            self.config.selector = SelectorKind::ALL[index];
+        }
+        Ok(())
+    }
+
+    fn ask_trash_command(&mut self) -> Result<()> {
+        let mut commands: Vec<String> = TRASH_COMMANDS
+            .iter()
+            .filter(|command| installed(command))
+            .map(|command| command.to_string())
+            .collect();
+        if commands.is_empty() {
+            eprintln!(
+                "no trash command found, install one of {:?}",
+                TRASH_COMMANDS
+            );
+        }
+        commands.push("other".to_string());
+        match self.selector.select("trash command", &commands)? {
+            Some(index) if index < commands.len() - 1 => {
+                self.config.trash_command = commands.swap_remove(index);
+            }
+            Some(_) => {
+                if let Some(command) = self.selector.input("trash command")? {
+                    self.config.trash_command = command;
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+fn installed(command: &str) -> bool {
+    which::which(command).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ScriptedSelector, TempDir};
+
+    #[test]
+    fn test_abort() {
+        let dir = TempDir::new("wizard-abort");
+        let path = dir.join("config.toml");
+        // the folder is entered, then merge tool, selector, trash command and abort
+        let selector =
+            ScriptedSelector::new(vec![Some(0), Some(0), None, Some(2)]).with_inputs(vec![None]);
+        let result = Wizard::new(&selector)
+            .with_syncthing_config(None)
+            .run(&path);
+        assert!(matches!(result, Err(Error::Aborted)));
+        assert!(!path.exists());
+
+        // an existing file is kept unless confirmed
+        std::fs::write(&path, "").unwrap();
+        let selector = ScriptedSelector::new(vec![Some(1)]);
+        let result = Wizard::new(&selector)
+            .with_syncthing_config(None)
+            .run(&path);
+        assert!(matches!(result, Err(Error::Aborted)));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = TempDir::new("wizard-write");
+        let path = dir.join("config.toml");
+        // the folder is entered, then merge tool, selector, trash command, review and save
+        let selector = ScriptedSelector::new(vec![Some(2), Some(0), None, Some(1), Some(0)])
+            .with_inputs(vec![Some(dir.display().to_string())]);
+        Wizard::new(&selector)
+            .with_syncthing_config(None)
+            .run(&path)
+            .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let config: Config = toml::from_str(&content).unwrap();
+        assert_eq!(config.working_directory, *dir);
+        assert_eq!(config.merge_tool, MergeTool::preset_names().nth(2).unwrap());
+        assert_eq!(config.selector, SelectorKind::ALL[0]);
+        assert_eq!(config.trash_command, Config::default().trash_command);
+        assert!(content.contains("# Merge tool preset"));
+    }
+}
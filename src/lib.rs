@@ -1,15 +1,36 @@
 pub mod app;
+pub mod artifacts;
+pub mod batch;
 pub mod cli;
 pub mod config;
 pub mod conflict;
 pub mod diff;
 pub mod duplicates;
-pub mod fzf;
+pub mod error;
+pub mod git;
+pub mod hooks;
+pub mod init;
+pub mod list;
+pub mod prune;
+pub mod rest;
+pub mod scan;
+pub mod selector;
+pub mod session;
+pub mod status;
+pub mod stignore;
+pub mod syncthing;
+#[cfg(test)]
+mod testing;
 pub mod trash;
+pub mod tui;
+pub mod versions;
+pub mod watch;
+
+use std::path::Path;
 
 use regex::Regex;
 
-pub type Result<T> = anyhow::Result<T>;
+pub use error::{Context, Error, Result};
 
 /// Returns a regex that matches Syncthing conflict files
 ///
@@ -39,3 +60,55 @@ pub fn sync_conflict_replace_regex_for_type(file_type: &str) -> Regex {
     Regex::new(&format!(r"\.sync-conflict-[A-Z0-9-]*\.{}$", file_type))
         .expect("Invalid regex pattern for replacing sync conflict suffix")
 }
+
+/// Returns a regex capturing the date, time and short device ID of a Syncthing conflict file
+///
+/// Conflict files are named `<name>.sync-conflict-<YYYYMMDD>-<HHMMSS>-<DEVICE>.<ext>`
+///
+/// # Example
+///
+/// ```
+/// use resolvething::sync_conflict_info_regex;
+/// let captures = sync_conflict_info_regex()
+///     .captures("Umzug.sync-conflict-20250412-111252-VNNIL2P.md")
+///     .unwrap();
+/// assert_eq!(&captures[1], "20250412");
+/// assert_eq!(&captures[2], "111252");
+/// assert_eq!(&captures[3], "VNNIL2P");
+/// ```
+pub fn sync_conflict_info_regex() -> Regex {
+    Regex::new(r"\.sync-conflict-(\d{8})-(\d{6})-([A-Z0-9]{7})")
+        .expect("Invalid regex pattern for sync conflict info")
+}
+
+/// Parse a number followed by an optional unit, e.g. `500M` or `7d`
+///
+/// `factor` maps the trimmed unit to its multiplier, `None` for unknown units.
+/// Returns `None` if the input is invalid or the result overflows.
+pub fn parse_scaled(input: &str, factor: impl Fn(&str) -> Option<u64>) -> Option<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    number
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(factor(unit.trim())?)
+}
+
+/// Rename a file, keeping it in its directory unless `new_name` contains a path
+///
+/// Returns the new path. Existing files are never overwritten.
+pub fn rename_file(path: &str, new_name: &str) -> Result<String> {
+    let target = match Path::new(path).parent() {
+        Some(dir) if !new_name.contains('/') => dir.join(new_name),
+        _ => Path::new(new_name).to_path_buf(),
+    };
+    if target.exists() {
+        return Err(Error::FileExists { path: target });
+    }
+    std::fs::rename(path, &target)
+        .with_context(|| format!("Failed to rename {} to {}", path, target.display()))?;
+    Ok(target.to_string_lossy().to_string())
+}
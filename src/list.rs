@@ -0,0 +1,261 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::artifacts::find_artifacts;
+use crate::config::Profile;
+use crate::conflict::ConflictFinder;
+use crate::duplicates::FclonesRunner;
+use crate::tui::format_size;
+use crate::{Context, Result};
+
+/// Items the `list` command prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListKind {
+    Conflicts,
+    Dupes,
+    Artifacts,
+}
+
+/// Output format of the `list` command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Aligned columns for humans
+    #[default]
+    Table,
+}
+
+/// An item together with the folder it was found in
+#[derive(Serialize)]
+pub(crate) struct Entry<'a, T> {
+    pub(crate) folder: &'a str,
+    #[serde(flatten)]
+    pub(crate) item: T,
+}
+
+/// Print all items of a kind found in the folders, without prompting
+pub fn list(
+    profiles: &[Profile],
+    kind: ListKind,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<()> {
+    match kind {
+        ListKind::Conflicts => {
+            let mut finders = Vec::new();
+            for profile in profiles {
+                finders.push((profile, ConflictFinder::for_profile(profile)?));
+            }
+            let entries = finders.iter().flat_map(|(profile, finder)| {
+                finder.conflicts.iter().map(|item| Entry {
+                    folder: &profile.name,
+                    item,
+                })
+            });
+            write_entries(
+                out,
+                format,
+                entries.collect(),
+                &["FOLDER", "DETECTED", "DEVICE", "SIZE", "CONFLICT COPY"],
+                |entry| {
+                    vec![
+                        entry.folder.to_string(),
+                        entry
+                            .item
+                            .timestamp
+                            .map_or_else(String::new, |time| time.to_string()),
+                        entry.item.device.clone().unwrap_or_default(),
+                        format_size(entry.item.modified_size.unwrap_or(0)),
+                        entry.item.modifiedfile.clone(),
+                    ]
+                },
+            )
+        }
+        ListKind::Dupes => {
+            let mut runners = Vec::new();
+            for profile in profiles {
+                runners.push((profile, FclonesRunner::for_profile(profile)?));
+            }
+            let entries = runners.iter().flat_map(|(profile, runner)| {
+                runner.duplicate_groups.iter().map(|item| Entry {
+                    folder: &profile.name,
+                    item,
+                })
+            });
+            write_entries(
+                out,
+                format,
+                entries.collect(),
+                &["FOLDER", "SIZE", "RECLAIMABLE", "FILES"],
+                |entry| {
+                    let files: Vec<String> = entry
+                        .item
+                        .files
+                        .iter()
+                        .map(|file| format!("{} ({})", file.path, file.file_type.label()))
+                        .collect();
+                    vec![
+                        entry.folder.to_string(),
+                        format_size(entry.item.size),
+                        format_size(entry.item.reclaimable()),
+                        files.join(", "),
+                    ]
+                },
+            )
+        }
+        ListKind::Artifacts => {
+            let mut entries = Vec::new();
+            for profile in profiles {
+                for item in find_artifacts(profile)? {
+                    entries.push(Entry {
+                        folder: &profile.name,
+                        item,
+                    });
+                }
+            }
+            write_entries(
+                out,
+                format,
+                entries,
+                &["FOLDER", "KIND", "SIZE", "AGE", "PATH"],
+                |entry| {
+                    vec![
+                        entry.folder.to_string(),
+                        entry.item.kind.label().to_string(),
+                        format_size(entry.item.size),
+                        entry.item.age.map_or_else(String::new, format_age),
+                        entry.item.path.clone(),
+                    ]
+                },
+            )
+        }
+    }
+}
+
+/// Write entries in the given format, `row` gives the table columns of an entry
+pub(crate) fn write_entries<T: Serialize>(
+    out: &mut impl Write,
+    format: OutputFormat,
+    entries: Vec<T>,
+    header: &[&str],
+    row: impl Fn(&T) -> Vec<String>,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut *out, &entries).context("Failed to write JSON")?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            for entry in &entries {
+                serde_json::to_writer(&mut *out, entry).context("Failed to write JSON")?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Table => {
+            let mut rows = vec![header.iter().map(|h| h.to_string()).collect()];
+            rows.extend(entries.iter().map(row));
+            write_table(out, &rows)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write rows with aligned columns, the last column is not padded
+fn write_table(out: &mut impl Write, rows: &[Vec<String>]) -> Result<()> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for row in rows {
+        let mut line = String::new();
+        for (column, cell) in row.iter().enumerate() {
+            if column + 1 == columns {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell, width = widths[column]));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Format a number of seconds as a short age, e.g. `3d` or `5h`
+pub fn format_age(seconds: u64) -> String {
+    match seconds {
+        s if s >= 86_400 => format!("{}d", s / 86_400),
+        s if s >= 3_600 => format!("{}h", s / 3_600),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Item {
+        path: &'static str,
+    }
+
+    fn entries() -> Vec<Entry<'static, Item>> {
+        vec![
+            Entry {
+                folder: "wiki",
+                item: Item { path: "a.md" },
+            },
+            Entry {
+                folder: "photos",
+                item: Item { path: "b.jpg" },
+            },
+        ]
+    }
+
+    #[test]
+    fn test_table() {
+        let mut out = Vec::new();
+        write_entries(
+            &mut out,
+            OutputFormat::Table,
+            entries(),
+            &["FOLDER", "FILE"],
+            |entry| vec![entry.folder.to_string(), entry.item.path.to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "FOLDER  FILE\nwiki    a.md\nphotos  b.jpg\n"
+        );
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let mut out = Vec::new();
+        write_entries(&mut out, OutputFormat::Ndjson, entries(), &[], |_| {
+            Vec::new()
+        })
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"folder\":\"wiki\",\"path\":\"a.md\"}\n{\"folder\":\"photos\",\"path\":\"b.jpg\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(30), "30s");
+        assert_eq!(format_age(7_200), "2h");
+        assert_eq!(format_age(3 * 86_400 + 5), "3d");
+    }
+}
@@ -1,23 +1,238 @@
-use anyhow::Result;
-use clap::Parser;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
 use resolvething::{
+    Error,
     app::App,
-    cli::{Cli, Commands},
+    artifacts::clean_artifacts,
+    batch::{RunOptions, Scan},
+    cli::{Cli, Commands, ConfigCommand, IgnoreCommand},
+    config::Config,
+    init::Wizard,
+    list::{OutputFormat, list},
+    prune::prune_versions,
+    selector::Prompt,
+    session::Session,
+    status::{Health, status},
+    versions,
+    watch::watch,
 };
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let app = App::new()?;
+fn main() -> ExitCode {
+    CompleteEnv::with_factory(Cli::command).complete();
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            Health::Error.into()
+        }
+    }
+}
+
+fn run(mut cli: Cli) -> Result<ExitCode> {
+    let scan = match cli.command.take().unwrap_or(Commands::All) {
+        Commands::Dupes => Some(Scan::Dupes),
+        Commands::Conflicts => Some(Scan::Conflicts),
+        Commands::All => Some(Scan::All),
+        Commands::Resume => None,
+        Commands::Watch { debounce } => {
+            let options = RunOptions {
+                non_interactive: true,
+                ..cli.run_options()
+            };
+            let mut app = App::new(load_config(&cli)?, options)?;
+            watch(&mut app, Duration::from_secs(debounce))?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Ignore { command } => {
+            ignore(command.unwrap_or(IgnoreCommand::List))?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Versions { file, list, format } => {
+            versions(&cli, &file, list, format)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Artifacts {
+            older_than,
+            yes,
+            format,
+        } => {
+            let mut config = load_config(&cli)?;
+            let profiles = config.run_profiles(&cli.run_options())?;
+            let selector = if cli.non_interactive {
+                None
+            } else {
+                Some(config.selector.build()?)
+            };
+            clean_artifacts(
+                &profiles,
+                older_than,
+                selector.as_deref(),
+                yes,
+                format,
+                &mut std::io::stdout().lock(),
+            )?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        command @ Commands::PruneVersions {
+            dry_run, format, ..
+        } => {
+            let profiles = load_config(&cli)?.run_profiles(&cli.run_options())?;
+            let policy = command.prune_policy();
+            prune_versions(
+                &profiles,
+                &policy,
+                dry_run,
+                format,
+                &mut std::io::stdout().lock(),
+            )?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Completions { shell } => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Man { out_dir } => {
+            std::fs::create_dir_all(&out_dir).context("Failed to create man page directory")?;
+            clap_mangen::generate_to(Cli::command(), &out_dir)
+                .context("Failed to write man pages")?;
+            eprintln!("wrote man pages to {}", out_dir.display());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Config { command } => {
+            config(&cli, command)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::List { kind, format } => {
+            let profiles = load_config(&cli)?.run_profiles(&cli.run_options())?;
+            list(&profiles, kind, format, &mut std::io::stdout().lock())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        command @ Commands::Status {
+            format, no_dupes, ..
+        } => {
+            let profiles = load_config(&cli)?.run_profiles(&cli.run_options())?;
+            let thresholds = command.thresholds();
+            let health = status(
+                &profiles,
+                &thresholds,
+                !no_dupes,
+                format,
+                &mut std::io::stdout().lock(),
+            )?;
+            return Ok(health.into());
+        }
+    };
+
+    let mut app = App::new(load_config(&cli)?, cli.run_options())?;
+    match scan {
+        Some(scan) => app.run(scan)?,
+        None => app.resume()?,
+    }
+    app.finish()?;
+
+    if cli.non_interactive {
+        println!("{}", app.summary().to_json());
+    }
 
-    if let Some(command) = cli.command {
-        match command {
-            Commands::Dupes => app.run_duplicate()?,
-            Commands::Conflicts => app.run_conflicts()?,
-            Commands::All => app.run_all()?,
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Load the effective configuration for the flags
+fn load_config(cli: &Cli) -> Result<Config> {
+    Config::load(cli.config.as_deref(), cli.config_overrides())
+        .context("Failed to load configuration")
+}
+
+/// List or browse the old versions of a file
+fn versions(cli: &Cli, file: &Path, list: bool, format: OutputFormat) -> Result<()> {
+    let config = load_config(cli)?;
+    let profiles = config.clone().run_profiles(&cli.run_options())?;
+    let file =
+        std::path::absolute(file).with_context(|| format!("Invalid path {}", file.display()))?;
+    let Some(profile) = versions::profile_of(&profiles, &file) else {
+        bail!("{} is not in a configured folder", file.display());
+    };
+    if list || cli.non_interactive {
+        let found = versions::find_versions(profile, &file)?;
+        versions::list_versions(&found, format, &mut std::io::stdout().lock())?;
+        return Ok(());
+    }
+    versions::browse(config.selector.build()?.as_ref(), profile, &file)?;
+    Ok(())
+}
+
+/// Manage the ignore list in the session file
+fn ignore(command: IgnoreCommand) -> Result<()> {
+    let mut session = Session::load()?;
+    match command {
+        IgnoreCommand::List => {
+            for pattern in session.ignore_patterns() {
+                println!("pattern: {}", pattern);
+            }
+            for key in &session.ignored {
+                println!("group: {}", key.replace('\n', ", "));
+            }
+            return Ok(());
+        }
+        IgnoreCommand::Add { pattern } => session.add_ignore_pattern(&pattern)?,
+        IgnoreCommand::Remove { pattern } => {
+            if !session.remove_ignore_pattern(&pattern)? {
+                bail!("No such ignore pattern: {}", pattern);
+            }
         }
-    } else {
-        app.run_all()?;
+        IgnoreCommand::Clear => session.clear_ignored(),
     }
+    session.save()?;
+    Ok(())
+}
 
+/// Inspect or create the configuration
+fn config(cli: &Cli, command: ConfigCommand) -> Result<()> {
+    if let ConfigCommand::Init = command {
+        let path = match &cli.config {
+            Some(path) => path.clone(),
+            None => Config::get_config_path()?,
+        };
+        return match Wizard::new(&Prompt).run(&path) {
+            Err(Error::Aborted) => {
+                eprintln!("aborted, nothing written");
+                Ok(())
+            }
+            result => Ok(result?),
+        };
+    }
+    let layers = Config::layers(cli.config.as_deref(), cli.config_overrides())?;
+    match command {
+        ConfigCommand::Init => unreachable!("handled above"),
+        ConfigCommand::Show => {
+            for (key, value) in layers.build()?.to_table()? {
+                println!("{} = {}  # {}", key, value, layers.source(&key));
+            }
+        }
+        ConfigCommand::Check => {
+            for (path, version) in layers.migrated() {
+                eprintln!(
+                    "{}: migrated from config version {}, run `resolvething config show` to see the result",
+                    path.display(),
+                    version
+                );
+            }
+            let problems = layers.build()?.check();
+            for problem in &problems {
+                println!("error: {}", problem);
+            }
+            if !problems.is_empty() {
+                bail!("{} problems found in configuration", problems.len());
+            }
+            println!("configuration ok");
+        }
+    }
     Ok(())
 }
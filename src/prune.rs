@@ -0,0 +1,279 @@
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::Serialize;
+
+use crate::config::Profile;
+use crate::list::{Entry, OutputFormat, write_entries};
+use crate::trash::Trash;
+use crate::tui::format_size;
+use crate::versions::{Version, all_versions};
+use crate::{Error, Result};
+
+/// Which old versions `prune-versions` keeps
+///
+/// A version is kept if any of the retention rules keeps it, or if there are none.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrunePolicy {
+    /// Keep the newest N versions of every file
+    pub keep_last: Option<usize>,
+    /// Keep versions archived less than this many days ago
+    pub keep_days: Option<u64>,
+    /// Remove the oldest versions of a folder until they use at most this many bytes
+    pub max_size: Option<u64>,
+    /// Remove versions identical to the current file or to the next newer version
+    pub drop_identical: bool,
+}
+
+/// An old version to remove and why
+#[derive(Debug, Clone, Serialize)]
+pub struct Removal {
+    #[serde(flatten)]
+    pub version: Version,
+    pub reason: &'static str,
+}
+
+impl PrunePolicy {
+    /// Versions of a folder to remove, `now` is the current local time
+    pub fn plan(&self, profile: &Profile, now: NaiveDateTime) -> Result<Vec<Removal>> {
+        let mut removals = Vec::new();
+        let mut kept = Vec::new();
+        for (file, versions) in all_versions(profile)? {
+            let mut retained = 0;
+            for (index, version) in versions.iter().enumerate() {
+                if self.drop_identical {
+                    let newer = match index {
+                        0 => file.as_path(),
+                        _ => versions[index - 1].path.as_path(),
+                    };
+                    if same_content(&version.path, newer) {
+                        let reason = match index {
+                            0 => "identical to current file",
+                            _ => "identical to newer version",
+                        };
+                        removals.push(Removal {
+                            version: version.clone(),
+                            reason,
+                        });
+                        continue;
+                    }
+                }
+                if self.retains(retained, version, now) {
+                    retained += 1;
+                    kept.push(version.clone());
+                } else {
+                    removals.push(Removal {
+                        version: version.clone(),
+                        reason: "outside retention",
+                    });
+                }
+            }
+        }
+
+        if let Some(max_size) = self.max_size {
+            kept.sort_by_key(|version| version.timestamp);
+            let mut total: u64 = kept.iter().map(|version| version.size).sum();
+            for version in kept {
+                if total <= max_size {
+                    break;
+                }
+                total -= version.size;
+                removals.push(Removal {
+                    version,
+                    reason: "over size cap",
+                });
+            }
+        }
+        Ok(removals)
+    }
+
+    /// Whether the retention rules keep a version, `newer` versions of the file are kept already
+    fn retains(&self, newer: usize, version: &Version, now: NaiveDateTime) -> bool {
+        if self.keep_last.is_none() && self.keep_days.is_none() {
+            return true;
+        }
+        self.keep_last.is_some_and(|last| newer < last)
+            || self.keep_days.is_some_and(|days| {
+                now - version.timestamp < TimeDelta::days(days.try_into().unwrap_or(i64::MAX))
+            })
+    }
+}
+
+/// Remove old versions as the policy says, or only print them on a dry run
+///
+/// Versions in read-only folders are listed but never removed.
+pub fn prune_versions(
+    profiles: &[Profile],
+    policy: &PrunePolicy,
+    dry_run: bool,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<()> {
+    let now = chrono::Local::now().naive_local();
+    let mut plans = Vec::new();
+    for profile in profiles {
+        plans.push((profile, policy.plan(profile, now)?));
+    }
+    let entries = plans.iter().flat_map(|(profile, removals)| {
+        removals.iter().map(|item| Entry {
+            folder: &profile.name,
+            item,
+        })
+    });
+    write_entries(
+        out,
+        format,
+        entries.collect(),
+        &["FOLDER", "ARCHIVED", "SIZE", "REASON", "PATH"],
+        |entry| {
+            vec![
+                entry.folder.to_string(),
+                entry.item.version.timestamp.to_string(),
+                format_size(entry.item.version.size),
+                entry.item.reason.to_string(),
+                entry.item.version.path.display().to_string(),
+            ]
+        },
+    )?;
+
+    let mut count = 0;
+    let mut size = 0;
+    let mut failed = 0;
+    let mut read_only = 0;
+    for (profile, removals) in &plans {
+        if profile.read_only {
+            read_only += removals.len();
+            continue;
+        }
+        for removal in removals {
+            let version = &removal.version;
+            if !dry_run
+                && let Err(e) = Trash::trash(&version.path.to_string_lossy(), &profile.config)
+            {
+                eprintln!("Error: {:#}", e);
+                failed += 1;
+                continue;
+            }
+            count += 1;
+            size += version.size;
+        }
+    }
+    if read_only > 0 {
+        eprintln!("leaving {} old versions in read-only folders", read_only);
+    }
+    let verb = if dry_run { "would remove" } else { "removed" };
+    eprintln!("{} {} old versions, {}", verb, count, format_size(size));
+    if failed > 0 {
+        return Err(Error::TrashFailed { failed });
+    }
+    Ok(())
+}
+
+/// Whether two files have the same content, false if either cannot be read
+fn same_content(a: &Path, b: &Path) -> bool {
+    let size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).ok();
+    match (size(a), size(b)) {
+        (Some(a_size), Some(b_size)) if a_size == b_size => {
+            matches!((std::fs::read(a), std::fs::read(b)), (Ok(a), Ok(b)) if a == b)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_plan() {
+        let dir = TempDir::new("prune");
+        let profile = Profile::for_test(&dir);
+        let versions_dir = &profile.versions_dir;
+        std::fs::create_dir_all(versions_dir).unwrap();
+        std::fs::write(dir.join("a.md"), "current").unwrap();
+        for (name, content) in [
+            ("a~20240105-120000.md", "current"),
+            ("a~20240104-120000.md", "four"),
+            ("a~20240103-120000.md", "four"),
+            ("a~20240102-120000.md", "two"),
+            ("a~20240101-120000.md", "one"),
+            ("b~20240101-120000.md", "bee"),
+        ] {
+            std::fs::write(versions_dir.join(name), content).unwrap();
+        }
+        let now = NaiveDateTime::parse_from_str("20240110-120000", "%Y%m%d-%H%M%S").unwrap();
+        let removed = |policy: PrunePolicy| -> Vec<(String, &'static str)> {
+            let mut removed: Vec<_> = policy
+                .plan(&profile, now)
+                .unwrap()
+                .into_iter()
+                .map(|removal| {
+                    let name = removal.version.path.file_name().unwrap();
+                    (name.to_string_lossy().to_string(), removal.reason)
+                })
+                .collect();
+            removed.sort();
+            removed
+        };
+
+        assert!(removed(PrunePolicy::default()).is_empty());
+        assert_eq!(
+            removed(PrunePolicy {
+                drop_identical: true,
+                keep_last: Some(1),
+                ..Default::default()
+            }),
+            [
+                ("a~20240101-120000.md".to_string(), "outside retention"),
+                ("a~20240102-120000.md".to_string(), "outside retention"),
+                (
+                    "a~20240103-120000.md".to_string(),
+                    "identical to newer version"
+                ),
+                (
+                    "a~20240105-120000.md".to_string(),
+                    "identical to current file"
+                ),
+            ]
+        );
+        assert_eq!(
+            removed(PrunePolicy {
+                keep_days: Some(7),
+                max_size: Some(10),
+                ..Default::default()
+            }),
+            [
+                ("a~20240101-120000.md".to_string(), "outside retention"),
+                ("a~20240102-120000.md".to_string(), "outside retention"),
+                ("a~20240103-120000.md".to_string(), "outside retention"),
+                ("a~20240104-120000.md".to_string(), "over size cap"),
+                ("b~20240101-120000.md".to_string(), "outside retention"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune_read_only() {
+        let dir = TempDir::new("prune-read-only");
+        let mut profile = Profile::for_test(&dir);
+        profile.read_only = true;
+        std::fs::create_dir_all(&profile.versions_dir).unwrap();
+        let version = profile.versions_dir.join("a~20240101-120000.md");
+        std::fs::write(&version, "one").unwrap();
+
+        let policy = PrunePolicy {
+            keep_last: Some(0),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        prune_versions(&[profile], &policy, false, OutputFormat::Table, &mut out).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("a~20240101-120000.md")
+        );
+        assert!(version.exists());
+    }
+}
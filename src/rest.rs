@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::{Config, expand_path};
+use crate::syncthing;
+use crate::{Context, Error, Result, bail};
+
+/// How long to wait for Syncthing before giving up on a request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Client for the REST API of the local Syncthing instance
+#[derive(Clone)]
+pub struct RestClient {
+    url: String,
+    api_key: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct FolderStatus {
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct FolderEntry {
+    id: String,
+    path: String,
+}
+
+impl RestClient {
+    /// Create a client for the API at `address`, e.g. `http://127.0.0.1:8384`
+    pub fn new(address: &str, api_key: &str) -> Self {
+        let url = if address.contains("://") {
+            address.trim_end_matches('/').to_string()
+        } else {
+            format!("http://{}", address.trim_end_matches('/'))
+        };
+        RestClient {
+            url,
+            api_key: api_key.to_string(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Client for the configured API, `None` if the API is disabled
+    ///
+    /// Address and API key not set in the config are read from Syncthing's config.xml.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if !config.syncthing_api {
+            return Ok(None);
+        }
+        let gui = match (&config.syncthing_address, &config.syncthing_api_key) {
+            (Some(_), Some(_)) => None,
+            _ => syncthing::load_gui(&config.syncthing_config_path()?)?,
+        };
+        let address = match (&config.syncthing_address, &gui) {
+            (Some(address), _) => address.clone(),
+            (None, Some(gui)) => gui.url(),
+            (None, None) => bail!("No Syncthing API address configured"),
+        };
+        let api_key = match (&config.syncthing_api_key, &gui) {
+            (Some(key), _) => key.clone(),
+            (None, Some(gui)) => gui.api_key.clone(),
+            (None, None) => bail!("No Syncthing API key configured"),
+        };
+        Ok(Some(Self::new(&address, &api_key)))
+    }
+
+    /// Check that Syncthing is running and accepts the API key
+    pub fn ping(&self) -> Result<()> {
+        self.agent
+            .get(&self.endpoint("/rest/system/ping"))
+            .set("X-API-Key", &self.api_key)
+            .call()
+            .with_context(|| format!("Syncthing is not reachable at {}", self.url))?;
+        Ok(())
+    }
+
+    /// Sync state of a folder, e.g. `idle`, `scanning` or `syncing`
+    pub fn folder_state(&self, id: &str) -> Result<String> {
+        let status: FolderStatus = self
+            .agent
+            .get(&self.endpoint("/rest/db/status"))
+            .query("folder", id)
+            .set("X-API-Key", &self.api_key)
+            .call()
+            .with_context(|| format!("Failed to get the status of folder {}", id))?
+            .into_json()?;
+        Ok(status.state)
+    }
+
+    /// ID of the Syncthing folder at `path`, `None` if it is not shared
+    pub fn folder_id(&self, path: &Path) -> Result<Option<String>> {
+        let folders: Vec<FolderEntry> = self
+            .agent
+            .get(&self.endpoint("/rest/config/folders"))
+            .set("X-API-Key", &self.api_key)
+            .call()
+            .context("Failed to get the Syncthing folders")?
+            .into_json()?;
+        let path = canonical(path);
+        Ok(folders
+            .into_iter()
+            .find(|folder| {
+                expand_path(Path::new(&folder.path)).is_ok_and(|p| canonical(&p) == path)
+            })
+            .map(|folder| folder.id))
+    }
+
+    /// Pause or resume a folder
+    pub fn set_paused(&self, id: &str, paused: bool) -> Result<()> {
+        // PATCH only changes the given fields of the folder config
+        self.agent
+            .request(
+                "PATCH",
+                &self.endpoint(&format!("/rest/config/folders/{}", id)),
+            )
+            .set("X-API-Key", &self.api_key)
+            .send_json(json!({ "paused": paused }))
+            .with_context(|| format!("Failed to set paused={} on folder {}", paused, id))?;
+        Ok(())
+    }
+
+    /// Ask Syncthing to rescan a folder so peers see changes right away
+    pub fn scan(&self, id: &str) -> Result<()> {
+        self.agent
+            .post(&self.endpoint("/rest/db/scan"))
+            .query("folder", id)
+            .set("X-API-Key", &self.api_key)
+            .call()
+            .with_context(|| format!("Failed to rescan folder {}", id))?;
+        Ok(())
+    }
+
+    /// Pause the idle folder at `path` until the returned guard is dropped
+    ///
+    /// Returns `None` if the path is not a Syncthing folder and fails if the folder is busy.
+    pub fn pause_folder(&self, path: &Path) -> Result<Option<PausedFolder>> {
+        let Some(id) = self.folder_id(path)? else {
+            return Ok(None);
+        };
+        let state = self.folder_state(&id)?;
+        if state != "idle" {
+            return Err(Error::FolderBusy { folder: id, state });
+        }
+        self.set_paused(&id, true)?;
+        Ok(Some(PausedFolder {
+            client: self.clone(),
+            id,
+        }))
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.url, path)
+    }
+}
+
+/// A folder paused through the REST API, resumed and rescanned on drop
+pub struct PausedFolder {
+    client: RestClient,
+    id: String,
+}
+
+impl Drop for PausedFolder {
+    fn drop(&mut self) {
+        let resumed = self
+            .client
+            .set_paused(&self.id, false)
+            .and_then(|()| self.client.scan(&self.id));
+        if let Err(e) = resumed {
+            eprintln!("warning: {:#}", e);
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Serve canned responses on a local port and record the request lines
+    fn mock_server(state: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut length = 0;
+                let mut authorized = false;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim().to_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    authorized |= header == "x-api-key: secret";
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request = request.trim().to_string();
+                let response = if !authorized {
+                    None
+                } else if request.starts_with("GET /rest/config/folders") {
+                    Some(r#"[{"id":"wiki","path":"/sync/wiki"}]"#.to_string())
+                } else if request.starts_with("GET /rest/db/status") {
+                    Some(format!(r#"{{"state":"{}"}}"#, state))
+                } else {
+                    Some("{}".to_string())
+                };
+                recorded.lock().unwrap().push(format!(
+                    "{} {}",
+                    request.split(" HTTP").next().unwrap(),
+                    String::from_utf8(body).unwrap()
+                ));
+                let reply = match response {
+                    Some(json) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        json.len(),
+                        json
+                    ),
+                    None => {
+                        "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                };
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        (address, requests)
+    }
+
+    #[test]
+    fn test_pause_and_rescan() {
+        let (address, requests) = mock_server("idle");
+        let client = RestClient::new(&address, "secret");
+        client.ping().unwrap();
+        let paused = client.pause_folder(Path::new("/sync/wiki")).unwrap();
+        assert!(paused.is_some());
+        assert!(
+            client
+                .pause_folder(Path::new("/sync/other"))
+                .unwrap()
+                .is_none()
+        );
+        drop(paused);
+        let requests = requests.lock().unwrap();
+        assert!(requests.contains(&"GET /rest/db/status?folder=wiki ".to_string()));
+        assert!(
+            requests.contains(&r#"PATCH /rest/config/folders/wiki {"paused":true}"#.to_string())
+        );
+        assert!(
+            requests.contains(&r#"PATCH /rest/config/folders/wiki {"paused":false}"#.to_string())
+        );
+        assert_eq!(requests.last().unwrap(), "POST /rest/db/scan?folder=wiki ");
+    }
+
+    #[test]
+    fn test_busy_folder() {
+        let (address, requests) = mock_server("syncing");
+        let client = RestClient::new(&format!("http://{}/", address), "secret");
+        assert!(client.pause_folder(Path::new("/sync/wiki")).is_err());
+        assert!(
+            !requests
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|r| r.starts_with("PATCH"))
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let (address, _) = mock_server("idle");
+        assert!(RestClient::new(&address, "wrong").ping().is_err());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        assert!(RestClient::new(&address, "secret").ping().is_err());
+    }
+}
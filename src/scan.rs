@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
+
+use jwalk::WalkDirGeneric;
+use regex::Regex;
+
+use crate::artifacts::ArtifactKind;
+use crate::config::Profile;
+use crate::stignore::StIgnore;
+use crate::{Result, sync_conflict_regex};
+
+/// Compiled once, every file of a folder is matched against it
+static CONFLICT_REGEX: LazyLock<Regex> = LazyLock::new(sync_conflict_regex);
+
+/// What a file found by a scan is, judged by its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// Conflict copy created by Syncthing
+    Conflict,
+    /// Leftover file of Syncthing or another tool
+    Artifact(ArtifactKind),
+    Regular,
+}
+
+impl FileKind {
+    /// Classify a file by its name
+    pub fn of(name: &str) -> Self {
+        if CONFLICT_REGEX.is_match(name) {
+            FileKind::Conflict
+        } else if let Some(kind) = ArtifactKind::of(name) {
+            FileKind::Artifact(kind)
+        } else {
+            FileKind::Regular
+        }
+    }
+}
+
+/// A file found by a scan
+#[derive(Debug, Clone)]
+pub struct ScannedFile {
+    pub path: PathBuf,
+    pub kind: FileKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// All files of a folder, classified in a single parallel walk
+///
+/// Old versions and files or directories ignored by Syncthing are left out, unreadable entries
+/// are skipped.
+#[derive(Debug, Default)]
+pub struct FolderScan {
+    pub files: Vec<ScannedFile>,
+}
+
+impl FolderScan {
+    /// Scan the folder of a profile
+    pub fn new(profile: &Profile) -> Result<Self> {
+        Self::scan(&profile.config.working_directory, &profile.versions_dir)
+    }
+
+    /// Scan `directory`, leaving out old versions in `versions_dir`
+    pub fn scan(directory: &Path, versions_dir: &Path) -> Result<Self> {
+        let ignore = Arc::new(StIgnore::load(directory)?);
+        let versions_dir = versions_dir.to_path_buf();
+        // directories are read and files classified on the rayon thread pool
+        let walk = WalkDirGeneric::<((), Option<ScannedFile>)>::new(directory)
+            .skip_hidden(false)
+            .process_read_dir(move |_, _, _, children| {
+                // ignored directories are dropped here, so they are never read
+                children.retain(|child| {
+                    child.as_ref().is_ok_and(|entry| {
+                        let path = entry.path();
+                        !path.starts_with(&versions_dir) && !ignore.is_ignored(&path)
+                    })
+                });
+                for entry in children.iter_mut().flatten() {
+                    if !entry.file_type.is_file() {
+                        continue;
+                    }
+                    let path = entry.path();
+                    let Ok(metadata) = entry.metadata() else {
+                        continue;
+                    };
+                    entry.client_state = Some(ScannedFile {
+                        kind: FileKind::of(&entry.file_name.to_string_lossy()),
+                        size: metadata.len(),
+                        modified: metadata.modified().ok(),
+                        path,
+                    });
+                }
+            });
+        let files = walk
+            .into_iter()
+            .filter_map(|entry| entry.ok()?.client_state)
+            .collect();
+        Ok(FolderScan { files })
+    }
+
+    /// Files of the given kind
+    pub fn of_kind(&self, kind: FileKind) -> impl Iterator<Item = &ScannedFile> {
+        self.files.iter().filter(move |file| file.kind == kind)
+    }
+
+    /// Conflict copies created by Syncthing
+    pub fn conflicts(&self) -> impl Iterator<Item = &ScannedFile> {
+        self.of_kind(FileKind::Conflict)
+    }
+
+    /// Leftover files with their kind
+    pub fn artifacts(&self) -> impl Iterator<Item = (&ScannedFile, ArtifactKind)> {
+        self.files.iter().filter_map(|file| match file.kind {
+            FileKind::Artifact(kind) => Some((file, kind)),
+            _ => None,
+        })
+    }
+
+    /// Non-empty files sharing their size with another file, only these can be duplicates
+    pub fn duplicate_candidates(&self) -> Vec<&Path> {
+        let mut sizes: HashMap<u64, usize> = HashMap::new();
+        for file in &self.files {
+            *sizes.entry(file.size).or_default() += 1;
+        }
+        self.files
+            .iter()
+            .filter(|file| file.size > 0 && sizes[&file.size] > 1)
+            .map(|file| file.path.as_path())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_scan() {
+        let dir = TempDir::new("scan");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::create_dir_all(dir.join(".stversions")).unwrap();
+        std::fs::write(dir.join("a.md"), "same").unwrap();
+        std::fs::write(dir.join("sub/b.md"), "same").unwrap();
+        std::fs::write(dir.join("c.md"), "unique").unwrap();
+        std::fs::write(dir.join("a.sync-conflict-20240101-120000-ABCDEFG.md"), "x").unwrap();
+        std::fs::write(dir.join("sub/.b.md.swp"), "").unwrap();
+        std::fs::write(dir.join("ignored.md"), "same").unwrap();
+        std::fs::write(dir.join(".stversions/a~20240101-120000.md"), "same").unwrap();
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        std::fs::write(dir.join("build/a.md"), "same").unwrap();
+        std::fs::write(dir.join(".stignore"), "ignored.md\nbuild\n").unwrap();
+
+        let scan = FolderScan::new(&Profile::for_test(&dir)).unwrap();
+        let names = |files: Vec<&Path>| -> Vec<String> {
+            let mut names: Vec<String> = files
+                .iter()
+                .map(|path| path.strip_prefix(&*dir).unwrap().display().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(scan.files.len(), 6);
+        assert_eq!(
+            names(scan.conflicts().map(|file| file.path.as_path()).collect()),
+            ["a.sync-conflict-20240101-120000-ABCDEFG.md"]
+        );
+        assert_eq!(
+            scan.artifacts().map(|(_, kind)| kind).collect::<Vec<_>>(),
+            [ArtifactKind::VimSwap]
+        );
+        assert_eq!(names(scan.duplicate_candidates()), ["a.md", "sub/b.md"]);
+    }
+}
@@ -0,0 +1,549 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::diff_files;
+use crate::tui::{Action, Progress, Tui, open_in_editor};
+use crate::{Context, Error, Result};
+
+/// Menu entries offered by list based selectors after the files
+const KEEP_SEVERAL_ENTRY: &str = "» keep several";
+const KEEP_ALL_ENTRY: &str = "» keep all and remember";
+const OPEN_ENTRY: &str = "» open in editor";
+const DIFF_ENTRY: &str = "» diff two files";
+const RENAME_ENTRY: &str = "» rename survivor";
+const MERGE_ENTRY: &str = "» merge in diff tool";
+const HISTORY_ENTRY: &str = "» pick a historical version";
+const SKIP_ENTRY: &str = "» skip";
+const QUIT_ENTRY: &str = "» quit";
+/// Entry ending a multi-selection
+const DONE_ENTRY: &str = "» done";
+
+/// Lets the user pick from a list of files
+pub trait Selector {
+    /// Let the user pick one of `items`
+    ///
+    /// Returns the index of the selected item, or `None` if the selection was aborted.
+    fn select(&self, prompt: &str, items: &[String]) -> Result<Option<usize>>;
+
+    /// Ask the user for a line of text, e.g. a new file name
+    ///
+    /// Returns `None` if the input was aborted or empty.
+    fn input(&self, prompt: &str) -> Result<Option<String>> {
+        read_line(&mut std::io::stdin().lock(), &mut std::io::stderr(), prompt)
+    }
+
+    /// Let the user choose what to do with a duplicate group or conflict
+    ///
+    /// The default implementation offers the files followed by a menu of actions.
+    /// Opening and diffing files return to the menu afterwards.
+    fn choose(
+        &self,
+        title: &str,
+        files: &[String],
+        progress: Progress,
+        allow_merge: bool,
+    ) -> Result<Action> {
+        let mut items = files.to_vec();
+        items.extend(
+            [
+                KEEP_SEVERAL_ENTRY,
+                KEEP_ALL_ENTRY,
+                OPEN_ENTRY,
+                DIFF_ENTRY,
+                RENAME_ENTRY,
+            ]
+            .map(String::from),
+        );
+        if allow_merge {
+            items.push(MERGE_ENTRY.to_string());
+            items.push(HISTORY_ENTRY.to_string());
+        }
+        items.push(SKIP_ENTRY.to_string());
+        items.push(QUIT_ENTRY.to_string());
+
+        let prompt = format!("{} {}/{}", title, progress.current, progress.total);
+        loop {
+            let Some(index) = self.select(&prompt, &items)? else {
+                return Ok(Action::Skip);
+            };
+            let action = match items[index].as_str() {
+                _ if index < files.len() => Action::Keep(index),
+                KEEP_SEVERAL_ENTRY => match self.select_several(files)?.as_slice() {
+                    [] => continue,
+                    [single] => Action::Keep(*single),
+                    several => Action::KeepSeveral(several.to_vec()),
+                },
+                KEEP_ALL_ENTRY => Action::KeepAll,
+                OPEN_ENTRY => {
+                    if let Some(file) = self.select("open", files)? {
+                        open_in_editor(&files[file])?;
+                    }
+                    continue;
+                }
+                DIFF_ENTRY => {
+                    if let Some(a) = self.select("diff from", files)?
+                        && let Some(b) = self.select("diff to", files)?
+                    {
+                        for line in diff_files(&files[a], &files[b])? {
+                            eprintln!("{}", line);
+                        }
+                    }
+                    continue;
+                }
+                RENAME_ENTRY => {
+                    let Some(file) = self.select("keep and rename", files)? else {
+                        continue;
+                    };
+                    match self.input("new name")? {
+                        Some(name) => Action::Rename(file, name),
+                        None => continue,
+                    }
+                }
+                MERGE_ENTRY => Action::Merge(0),
+                HISTORY_ENTRY => Action::History,
+                QUIT_ENTRY => Action::Quit,
+                _ => Action::Skip,
+            };
+            return Ok(action);
+        }
+    }
+
+    /// Let the user pick several files, one at a time, until done
+    fn select_several(&self, files: &[String]) -> Result<Vec<usize>> {
+        let mut selected = Vec::new();
+        loop {
+            let mut items: Vec<String> = files
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !selected.contains(index))
+                .map(|(_, file)| file.clone())
+                .collect();
+            items.push(DONE_ENTRY.to_string());
+            let Some(index) = self.select("keep", &items)? else {
+                return Ok(Vec::new());
+            };
+            match files.iter().position(|file| *file == items[index]) {
+                Some(file) => selected.push(file),
+                None => {
+                    selected.sort();
+                    return Ok(selected);
+                }
+            }
+        }
+    }
+}
+
+/// Selector backend to use, configured in [`crate::config::Config`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectorKind {
+    /// Built-in terminal UI in a terminal, rofi or dmenu in graphical sessions,
+    /// numbered prompt otherwise
+    #[default]
+    Auto,
+    /// Built-in terminal UI
+    Tui,
+    /// fzf with a file preview
+    Fzf,
+    /// skim (`sk`) with a file preview
+    Skim,
+    /// rofi in dmenu mode
+    Rofi,
+    /// dmenu
+    Dmenu,
+    /// Numbered list read from stdin
+    Prompt,
+}
+
+impl SelectorKind {
+    /// All backends, in the order they are offered
+    pub const ALL: [SelectorKind; 7] = [
+        SelectorKind::Auto,
+        SelectorKind::Tui,
+        SelectorKind::Fzf,
+        SelectorKind::Skim,
+        SelectorKind::Rofi,
+        SelectorKind::Dmenu,
+        SelectorKind::Prompt,
+    ];
+
+    /// Name of the backend as written in the config
+    pub fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+
+    /// External command required by the backend
+    pub fn command(&self) -> Option<&'static str> {
+        match self {
+            SelectorKind::Fzf => Some("fzf"),
+            SelectorKind::Skim => Some("sk"),
+            SelectorKind::Rofi => Some("rofi"),
+            SelectorKind::Dmenu => Some("dmenu"),
+            SelectorKind::Auto | SelectorKind::Tui | SelectorKind::Prompt => None,
+        }
+    }
+
+    /// Resolve [`SelectorKind::Auto`] to the best backend available
+    pub fn detect() -> SelectorKind {
+        let installed =
+            |kind: SelectorKind| kind.command().is_some_and(|c| which::which(c).is_ok());
+        let graphical =
+            std::env::var_os("WAYLAND_DISPLAY").is_some() || std::env::var_os("DISPLAY").is_some();
+
+        if std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+            SelectorKind::Tui
+        } else if graphical && installed(SelectorKind::Rofi) {
+            SelectorKind::Rofi
+        } else if graphical && installed(SelectorKind::Dmenu) {
+            SelectorKind::Dmenu
+        } else {
+            SelectorKind::Prompt
+        }
+    }
+
+    /// Create the selector, failing if its command is not installed
+    pub fn build(self) -> Result<Box<dyn Selector>> {
+        let kind = match self {
+            SelectorKind::Auto => Self::detect(),
+            kind => kind,
+        };
+        if let Some(command) = kind.command()
+            && which::which(command).is_err()
+        {
+            return Err(Error::MissingDependency {
+                command: command.to_string(),
+            });
+        }
+        Ok(match kind {
+            SelectorKind::Auto | SelectorKind::Tui => Box::new(TuiSelector),
+            SelectorKind::Fzf => Box::new(Fzf::new("fzf")),
+            SelectorKind::Skim => Box::new(Fzf::new("sk")),
+            SelectorKind::Rofi => Box::new(GraphicalMenu::rofi()),
+            SelectorKind::Dmenu => Box::new(GraphicalMenu::dmenu()),
+            SelectorKind::Prompt => Box::new(Prompt),
+        })
+    }
+}
+
+/// Built-in terminal UI, see [`Tui`]
+pub struct TuiSelector;
+
+impl Selector for TuiSelector {
+    fn select(&self, prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        Tui::pick(prompt, items)
+    }
+
+    fn choose(
+        &self,
+        title: &str,
+        files: &[String],
+        progress: Progress,
+        allow_merge: bool,
+    ) -> Result<Action> {
+        Tui::choose(title, files, progress, allow_merge)
+    }
+}
+
+/// fzf or a compatible fuzzy finder such as skim
+pub struct Fzf {
+    command: &'static str,
+}
+
+impl Fzf {
+    pub fn new(command: &'static str) -> Self {
+        Fzf { command }
+    }
+
+    fn preview_command() -> &'static str {
+        if which::which("bat").is_ok() {
+            "bat --style=plain --paging=never --color=always {}"
+        } else {
+            "cat {}"
+        }
+    }
+}
+
+impl Selector for Fzf {
+    fn select(&self, prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        let child = Command::new(self.command)
+            .arg("--prompt")
+            .arg(format!("{}> ", prompt))
+            .arg("--preview")
+            .arg(Self::preview_command())
+            .arg("--preview-window")
+            .arg(if termsize::get().is_some_and(|size| size.cols < 80) {
+                "down:50%"
+            } else {
+                "right:50%"
+            })
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::spawn(self.command, e))?;
+        run_with_items(child, items)
+    }
+}
+
+/// Graphical menu for sessions without a terminal, e.g. launched from a hotkey
+pub struct GraphicalMenu {
+    command: &'static str,
+    args: &'static [&'static str],
+}
+
+impl GraphicalMenu {
+    pub fn rofi() -> Self {
+        GraphicalMenu {
+            command: "rofi",
+            args: &["-dmenu", "-i", "-p"],
+        }
+    }
+
+    pub fn dmenu() -> Self {
+        GraphicalMenu {
+            command: "dmenu",
+            args: &["-i", "-l", "20", "-p"],
+        }
+    }
+}
+
+impl Selector for GraphicalMenu {
+    fn select(&self, prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        let child = Command::new(self.command)
+            .args(self.args)
+            .arg(prompt)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::spawn(self.command, e))?;
+        run_with_items(child, items)
+    }
+
+    fn input(&self, prompt: &str) -> Result<Option<String>> {
+        // without items the menu returns whatever was typed
+        let child = Command::new(self.command)
+            .args(self.args)
+            .arg(prompt)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::spawn(self.command, e))?;
+        let output = child
+            .wait_with_output()
+            .context("Failed to read selector output")?;
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((output.status.success() && !text.is_empty()).then_some(text))
+    }
+}
+
+/// Write the items to the stdin of a menu process and map its output back to an index
+fn run_with_items(mut child: std::process::Child, items: &[String]) -> Result<Option<usize>> {
+    {
+        let stdin = child
+            .stdin
+            .as_mut()
+            .context("Failed to open selector stdin")?;
+        for item in items {
+            writeln!(stdin, "{}", item).context("Failed to write to selector stdin")?;
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .context("Failed to read selector output")?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let selected = String::from_utf8_lossy(&output.stdout);
+    let selected = selected.lines().next().unwrap_or_default();
+    Ok(items.iter().position(|item| item == selected))
+}
+
+/// Show a prompt on `output` and read a line from `input`
+fn read_line(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    prompt: &str,
+) -> Result<Option<String>> {
+    write!(output, "{}: ", prompt)?;
+    output.flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let line = line.trim();
+    Ok((!line.is_empty()).then(|| line.to_string()))
+}
+
+/// Numbered list on stderr, answered on stdin
+///
+/// Works on dumb terminals and over pipes.
+pub struct Prompt;
+
+impl Prompt {
+    /// Show the numbered items on `output` and read the chosen number from `input`
+    ///
+    /// An empty answer or end of input aborts the selection.
+    pub fn select_from(
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+        prompt: &str,
+        items: &[String],
+    ) -> Result<Option<usize>> {
+        for (index, item) in items.iter().enumerate() {
+            writeln!(output, "{:>3}) {}", index + 1, item)?;
+        }
+        loop {
+            write!(output, "{} [1-{}, empty to abort]: ", prompt, items.len())?;
+            output.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                return Ok(None);
+            }
+            let answer = answer.trim();
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            match answer.parse::<usize>() {
+                Ok(number) if (1..=items.len()).contains(&number) => return Ok(Some(number - 1)),
+                _ => writeln!(output, "invalid choice: {}", answer)?,
+            }
+        }
+    }
+}
+
+impl Selector for Prompt {
+    fn select(&self, prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        Self::select_from(
+            &mut std::io::stdin().lock(),
+            &mut std::io::stderr(),
+            prompt,
+            items,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Selector replaying fixed answers, for testing the default `choose`
+    struct Fixed(RefCell<Vec<Option<usize>>>, Option<String>);
+
+    impl Fixed {
+        fn new(answers: &[Option<usize>]) -> Self {
+            Fixed(RefCell::new(answers.to_vec()), None)
+        }
+    }
+
+    impl Selector for Fixed {
+        fn select(&self, _prompt: &str, _items: &[String]) -> Result<Option<usize>> {
+            Ok(self.0.borrow_mut().remove(0))
+        }
+
+        fn input(&self, _prompt: &str) -> Result<Option<String>> {
+            Ok(self.1.clone())
+        }
+    }
+
+    fn items() -> Vec<String> {
+        vec!["a.md".to_string(), "b.md".to_string()]
+    }
+
+    #[test]
+    fn test_prompt_select() {
+        let mut output = Vec::new();
+        let selected =
+            Prompt::select_from(&mut "2\n".as_bytes(), &mut output, "pick", &items()).unwrap();
+        assert_eq!(selected, Some(1));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("  1) a.md"));
+        assert!(output.contains("  2) b.md"));
+    }
+
+    #[test]
+    fn test_prompt_retries_invalid_choice() {
+        let mut output = Vec::new();
+        let selected =
+            Prompt::select_from(&mut "7\nx\n1\n".as_bytes(), &mut output, "pick", &items())
+                .unwrap();
+        assert_eq!(selected, Some(0));
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .contains("invalid choice: 7")
+        );
+    }
+
+    #[test]
+    fn test_prompt_abort() {
+        let mut output = Vec::new();
+        assert_eq!(
+            Prompt::select_from(&mut "\n".as_bytes(), &mut output, "pick", &items()).unwrap(),
+            None
+        );
+        assert_eq!(
+            Prompt::select_from(&mut "".as_bytes(), &mut output, "pick", &items()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_choose_menu_entries() {
+        let progress = Progress::new(1, 1);
+        let choose = |answers: &[Option<usize>], merge| {
+            Fixed::new(answers)
+                .choose("t", &items(), progress, merge)
+                .unwrap()
+        };
+        // a.md, b.md, keep several, keep all, open, diff, rename, merge, history, skip, quit
+        assert_eq!(choose(&[Some(1)], true), Action::Keep(1));
+        assert_eq!(choose(&[Some(3)], true), Action::KeepAll);
+        assert_eq!(choose(&[Some(7)], true), Action::Merge(0));
+        assert_eq!(choose(&[Some(8)], true), Action::History);
+        assert_eq!(choose(&[Some(9)], true), Action::Skip);
+        assert_eq!(choose(&[Some(10)], true), Action::Quit);
+        assert_eq!(choose(&[Some(7)], false), Action::Skip);
+        assert_eq!(choose(&[Some(8)], false), Action::Quit);
+        assert_eq!(choose(&[None], false), Action::Skip);
+    }
+
+    #[test]
+    fn test_choose_keep_several() {
+        let progress = Progress::new(1, 1);
+        // pick b.md, then a.md from the remaining list, then done
+        let selector = Fixed::new(&[Some(2), Some(1), Some(0), Some(0)]);
+        assert_eq!(
+            selector.choose("t", &items(), progress, false).unwrap(),
+            Action::KeepSeveral(vec![0, 1])
+        );
+    }
+
+    #[test]
+    fn test_choose_rename() {
+        let progress = Progress::new(1, 1);
+        let selector = Fixed(
+            RefCell::new(vec![Some(6), Some(1)]),
+            Some("c.md".to_string()),
+        );
+        assert_eq!(
+            selector.choose("t", &items(), progress, false).unwrap(),
+            Action::Rename(1, "c.md".to_string())
+        );
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut output = Vec::new();
+        assert_eq!(
+            read_line(&mut " new.md \n".as_bytes(), &mut output, "name").unwrap(),
+            Some("new.md".to_string())
+        );
+        assert_eq!(
+            read_line(&mut "\n".as_bytes(), &mut output, "name").unwrap(),
+            None
+        );
+    }
+}
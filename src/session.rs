@@ -0,0 +1,212 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::batch::{ItemKind, Scan};
+use crate::{Context, Result};
+
+/// Maximum number of decisions kept in the session file
+const MAX_DECISIONS: usize = 1000;
+
+/// A decision the user made about a conflict or duplicate group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision {
+    pub kind: ItemKind,
+    pub files: Vec<String>,
+    pub action: String,
+    /// Local time of the decision in RFC 3339 format
+    pub time: String,
+}
+
+/// State carried over between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Command of the last run, continued by `resume`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Scan>,
+    /// The last run was quit before all items were offered
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Items the user skipped, only offered again by `resume`
+    #[serde(default)]
+    pub deferred: BTreeSet<String>,
+    /// Items the user chose to keep as they are, never offered again
+    #[serde(default)]
+    pub ignored: BTreeSet<String>,
+    /// Glob patterns of files never offered again, e.g. `**/template.md`
+    #[serde(default)]
+    ignore_patterns: Vec<String>,
+    /// Decisions made in earlier runs, oldest first
+    #[serde(default)]
+    pub decisions: Vec<Decision>,
+    /// The ignore patterns compiled, rebuilt whenever they change
+    #[serde(skip)]
+    ignore_set: GlobSet,
+}
+
+impl Session {
+    /// Key identifying a conflict or duplicate group by its files
+    pub fn key(files: &[String]) -> String {
+        let mut files = files.to_vec();
+        files.sort();
+        files.join("\n")
+    }
+
+    /// Get the session file path
+    pub fn get_session_path() -> Result<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("resolvething").join("session.toml"))
+            .context("Could not determine state directory")
+    }
+
+    /// Load the session from disk, starting a new one if there is none
+    pub fn load() -> Result<Self> {
+        let path = Self::get_session_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path).context("Failed to read session file")?;
+        let mut session: Self = toml::from_str(&content).context("Failed to parse session file")?;
+        session.build_ignore_set()?;
+        Ok(session)
+    }
+
+    /// Save the session to disk
+    pub fn save(&self) -> Result<()> {
+        let path = Self::get_session_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("Failed to create state directory")?;
+        }
+        let content = toml::to_string(self).context("Failed to serialize session")?;
+        std::fs::write(&path, content).context("Failed to write session file")
+    }
+
+    /// Check whether the user asked to never see an item again
+    pub fn is_ignored(&self, key: &str) -> bool {
+        self.ignored.contains(key)
+            || (!self.ignore_patterns.is_empty()
+                && key.split('\n').all(|file| self.ignore_set.is_match(file)))
+    }
+
+    /// Check whether an item was skipped earlier and waits in the pending queue
+    pub fn is_deferred(&self, key: &str) -> bool {
+        self.deferred.contains(key)
+    }
+
+    /// Glob patterns of files never offered again
+    pub fn ignore_patterns(&self) -> &[String] {
+        &self.ignore_patterns
+    }
+
+    /// Never offer groups whose files all match the glob pattern again
+    pub fn add_ignore_pattern(&mut self, pattern: &str) -> Result<()> {
+        Glob::new(pattern).with_context(|| format!("Invalid ignore pattern: {}", pattern))?;
+        if !self.ignore_patterns.iter().any(|p| p == pattern) {
+            self.ignore_patterns.push(pattern.to_string());
+        }
+        self.build_ignore_set()
+    }
+
+    /// Offer files matching the pattern again, false if there is no such pattern
+    pub fn remove_ignore_pattern(&mut self, pattern: &str) -> Result<bool> {
+        let before = self.ignore_patterns.len();
+        self.ignore_patterns.retain(|p| p != pattern);
+        self.build_ignore_set()?;
+        Ok(self.ignore_patterns.len() < before)
+    }
+
+    /// Offer all ignored items again
+    pub fn clear_ignored(&mut self) {
+        self.ignore_patterns.clear();
+        self.ignored.clear();
+        self.ignore_set = GlobSet::empty();
+    }
+
+    fn build_ignore_set(&mut self) -> Result<()> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.ignore_patterns {
+            builder.add(
+                Glob::new(pattern)
+                    .with_context(|| format!("Invalid ignore pattern: {}", pattern))?,
+            );
+        }
+        self.ignore_set = builder.build().context("Failed to build ignore patterns")?;
+        Ok(())
+    }
+
+    /// Record a decision, dropping the item from the pending queue
+    pub fn decide(&mut self, kind: ItemKind, files: Vec<String>, action: &str) {
+        self.deferred.remove(&Self::key(&files));
+        self.decisions.push(Decision {
+            kind,
+            files,
+            action: action.to_string(),
+            time: Local::now().to_rfc3339(),
+        });
+        if self.decisions.len() > MAX_DECISIONS {
+            let excess = self.decisions.len() - MAX_DECISIONS;
+            self.decisions.drain(..excess);
+        }
+    }
+
+    /// Drop deferred items whose files are gone, they were resolved elsewhere
+    pub fn prune(&mut self) {
+        self.deferred
+            .retain(|key| key.split('\n').all(|file| Path::new(file).exists()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_ignores_order() {
+        let a = Session::key(&["b.md".to_string(), "a.md".to_string()]);
+        let b = Session::key(&["a.md".to_string(), "b.md".to_string()]);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut session = Session::default();
+        session.deferred.insert("a.md\nb.md".to_string());
+        session.ignored.insert("c.md\nd.md".to_string());
+        session.decide(ItemKind::Conflict, vec!["e.md".to_string()], "merged");
+
+        let parsed: Session = toml::from_str(&toml::to_string(&session).unwrap()).unwrap();
+        assert!(parsed.is_deferred("a.md\nb.md"));
+        assert!(parsed.is_ignored("c.md\nd.md"));
+        assert!(!parsed.is_ignored("a.md\nb.md"));
+        assert_eq!(parsed.decisions.len(), 1);
+        assert_eq!(parsed.decisions[0].action, "merged");
+    }
+
+    #[test]
+    fn test_ignore_patterns() {
+        let mut session = Session::default();
+        session.add_ignore_pattern("**/template.md").unwrap();
+        assert!(session.is_ignored("/wiki/a/template.md\n/wiki/b/template.md"));
+        assert!(!session.is_ignored("/wiki/a/template.md\n/wiki/b/notes.md"));
+
+        assert!(session.remove_ignore_pattern("**/template.md").unwrap());
+        assert!(!session.remove_ignore_pattern("**/template.md").unwrap());
+        assert!(!session.is_ignored("/wiki/a/template.md\n/wiki/b/template.md"));
+    }
+
+    #[test]
+    fn test_decide_removes_deferred() {
+        let mut session = Session::default();
+        session.deferred.insert("a.md\nb.md".to_string());
+        session.decide(
+            ItemKind::Duplicate,
+            vec!["b.md".to_string(), "a.md".to_string()],
+            "kept a.md",
+        );
+        assert!(!session.is_deferred("a.md\nb.md"));
+    }
+}
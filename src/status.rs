@@ -0,0 +1,228 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::artifacts::artifacts_in;
+use crate::config::Profile;
+use crate::conflict::ConflictFinder;
+use crate::duplicates::FclonesRunner;
+use crate::list::{Entry, OutputFormat, write_entries};
+use crate::scan::FolderScan;
+use crate::tui::format_size;
+use crate::{Error, Result, parse_scaled};
+
+/// Overall health reported by `status`, used as the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// Nothing above the thresholds
+    Clean = 0,
+    /// Items above the thresholds wait to be resolved
+    Pending = 1,
+    /// A folder could not be scanned
+    Error = 2,
+}
+
+impl From<Health> for ExitCode {
+    fn from(health: Health) -> Self {
+        ExitCode::from(health as u8)
+    }
+}
+
+/// Counts above which `status` reports pending items
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    pub conflicts: usize,
+    pub dupes: usize,
+    pub artifacts: usize,
+    /// Bytes of old versions per folder, unlimited if `None`
+    pub versions: Option<u64>,
+}
+
+/// Counts of pending items in a folder
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FolderStatus {
+    pub conflicts: usize,
+    /// Duplicate groups, `None` if duplicates were not scanned
+    pub dupes: Option<usize>,
+    pub artifacts: usize,
+    /// Bytes used by old versions
+    pub versions: u64,
+    /// Why the folder could not be scanned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FolderStatus {
+    /// Count the items of a folder, scanning for duplicates only if `dupes` is set
+    pub fn scan(profile: &Profile, dupes: bool) -> Self {
+        let mut status = FolderStatus::default();
+        let scan = |status: &mut FolderStatus| -> Result<()> {
+            let directory = &profile.config.working_directory;
+            if !directory.is_dir() {
+                return Err(Error::NotFound {
+                    path: directory.clone(),
+                });
+            }
+            let files = FolderScan::new(profile)?;
+            status.conflicts = ConflictFinder::from_scan(profile, &files)?.conflicts.len();
+            status.artifacts = artifacts_in(&files)?.len();
+            status.versions = directory_size(&profile.versions_dir);
+            if dupes {
+                status.dupes = Some(FclonesRunner::from_scan(&files)?.duplicate_groups.len());
+            }
+            Ok(())
+        };
+        if let Err(e) = scan(&mut status) {
+            status.error = Some(format!("{:#}", e));
+        }
+        status
+    }
+
+    /// Health of the folder given the thresholds
+    pub fn health(&self, thresholds: &Thresholds) -> Health {
+        if self.error.is_some() {
+            Health::Error
+        } else if self.conflicts > thresholds.conflicts
+            || self.dupes.unwrap_or(0) > thresholds.dupes
+            || self.artifacts > thresholds.artifacts
+            || thresholds
+                .versions
+                .is_some_and(|limit| self.versions > limit)
+        {
+            Health::Pending
+        } else {
+            Health::Clean
+        }
+    }
+}
+
+/// Print the status of every folder and return the worst health
+pub fn status(
+    profiles: &[Profile],
+    thresholds: &Thresholds,
+    dupes: bool,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<Health> {
+    let statuses: Vec<FolderStatus> = profiles
+        .iter()
+        .map(|profile| FolderStatus::scan(profile, dupes))
+        .collect();
+    let health = statuses
+        .iter()
+        .map(|status| status.health(thresholds))
+        .max()
+        .unwrap_or(Health::Clean);
+    let entries = profiles
+        .iter()
+        .zip(&statuses)
+        .map(|(profile, item)| Entry {
+            folder: &profile.name,
+            item,
+        })
+        .collect();
+    write_entries(
+        out,
+        format,
+        entries,
+        &[
+            "FOLDER",
+            "CONFLICTS",
+            "DUPES",
+            "ARTIFACTS",
+            "VERSIONS",
+            "STATUS",
+        ],
+        |entry| {
+            let status = entry.item;
+            vec![
+                entry.folder.to_string(),
+                status.conflicts.to_string(),
+                status.dupes.map_or("-".to_string(), |d| d.to_string()),
+                status.artifacts.to_string(),
+                format_size(status.versions),
+                match &status.error {
+                    Some(error) => format!("error: {}", error),
+                    None => format!("{:?}", status.health(thresholds)).to_lowercase(),
+                },
+            ]
+        },
+    )?;
+    Ok(health)
+}
+
+/// Total size of the files in a directory, 0 if it does not exist
+fn directory_size(directory: &Path) -> u64 {
+    WalkDir::new(directory)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Parse a size like `500M` or `2G`, plain numbers are bytes
+pub fn parse_size(size: &str) -> Result<u64> {
+    let factor = |unit: &str| match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => Some(1),
+        "k" | "kb" | "kib" => Some(1 << 10),
+        "m" | "mb" | "mib" => Some(1 << 20),
+        "g" | "gb" | "gib" => Some(1 << 30),
+        "t" | "tb" | "tib" => Some(1 << 40),
+        _ => None,
+    };
+    match parse_scaled(size, factor) {
+        Some(bytes) => Ok(bytes),
+        None => Err(Error::InvalidConfigValue {
+            key: "size".to_string(),
+            value: size.trim().to_string(),
+            expected: "use e.g. 500M or 2G".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("1GiB").unwrap(), 1 << 30);
+        assert_eq!(parse_size("3 m").unwrap(), 3 << 20);
+        assert!(parse_size("G").is_err());
+        assert_eq!(parse_size("2GB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("500MB").unwrap(), 500 << 20);
+        assert_eq!(parse_size("4 KiB").unwrap(), 4 << 10);
+        assert!(parse_size("5X").is_err());
+        assert!(parse_size("5ib").is_err());
+        assert!(parse_size("5IB").is_err());
+        assert!(parse_size("5GBB").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn test_health() {
+        let thresholds = Thresholds {
+            conflicts: 1,
+            versions: Some(100),
+            ..Default::default()
+        };
+        let mut status = FolderStatus {
+            conflicts: 1,
+            versions: 100,
+            ..Default::default()
+        };
+        assert_eq!(status.health(&thresholds), Health::Clean);
+        status.dupes = Some(1);
+        assert_eq!(status.health(&thresholds), Health::Pending);
+        status.error = Some("gone".to_string());
+        assert_eq!(status.health(&thresholds), Health::Error);
+    }
+}
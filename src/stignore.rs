@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Context, Error, Result};
+use globset::{GlobBuilder, GlobMatcher};
+
+/// Name of the Syncthing ignore file at the root of a folder
+pub const STIGNORE_FILE: &str = ".stignore";
+
+/// A single compiled line of a `.stignore` file
+struct IgnorePattern {
+    matchers: Vec<GlobMatcher>,
+    /// Pattern was prefixed with `!`, matching files are included again
+    negated: bool,
+    /// Pattern was prefixed with `(?d)`, matching files may be deleted by Syncthing
+    deletable: bool,
+}
+
+impl IgnorePattern {
+    /// Compile a pattern, the error describes what is wrong with it
+    fn parse(line: &str) -> std::result::Result<Self, String> {
+        let mut pattern = line;
+        let mut negated = false;
+        let mut case_insensitive = false;
+        let mut deletable = false;
+
+        // prefixes may be combined in any order, e.g. `!(?i)foo` or `(?d)(?i)foo`
+        loop {
+            if let Some(rest) = pattern.strip_prefix('!') {
+                negated = true;
+                pattern = rest;
+            } else if let Some(rest) = pattern.strip_prefix("(?i)") {
+                case_insensitive = true;
+                pattern = rest;
+            } else if let Some(rest) = pattern.strip_prefix("(?d)") {
+                deletable = true;
+                pattern = rest;
+            } else {
+                break;
+            }
+        }
+
+        let pattern = pattern.trim_end_matches('/');
+        if pattern.is_empty() || pattern == "/" {
+            return Err(format!("empty ignore pattern '{}'", line));
+        }
+
+        let globs = match pattern.strip_prefix('/') {
+            Some(anchored) => vec![anchored.to_string(), format!("{}/**", anchored)],
+            None => vec![
+                pattern.to_string(),
+                format!("{}/**", pattern),
+                format!("**/{}", pattern),
+                format!("**/{}/**", pattern),
+            ],
+        };
+
+        let matchers = globs
+            .iter()
+            .map(|glob| {
+                GlobBuilder::new(glob)
+                    .literal_separator(true)
+                    .case_insensitive(case_insensitive)
+                    .build()
+                    .map(|glob| glob.compile_matcher())
+                    .map_err(|e| format!("invalid ignore pattern '{}': {}", line, e.kind()))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(IgnorePattern {
+            matchers,
+            negated,
+            deletable,
+        })
+    }
+
+    fn is_match(&self, relative: &str) -> bool {
+        self.matchers.iter().any(|m| m.is_match(relative))
+    }
+}
+
+/// Ignore rules of a Syncthing folder, parsed from its `.stignore` file
+#[derive(Default)]
+pub struct StIgnore {
+    root: PathBuf,
+    patterns: Vec<IgnorePattern>,
+}
+
+impl StIgnore {
+    /// Load the `.stignore` file of the folder at `root`
+    ///
+    /// A folder without a `.stignore` file ignores nothing.
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut ignore = StIgnore {
+            root: root.to_path_buf(),
+            patterns: Vec::new(),
+        };
+        let path = root.join(STIGNORE_FILE);
+        if path.is_file() {
+            ignore.load_file(&path, &mut Vec::new())?;
+        }
+        Ok(ignore)
+    }
+
+    /// Parse ignore rules from a string, resolving `#include` relative to `root`
+    pub fn parse(root: &Path, content: &str) -> Result<Self> {
+        let mut ignore = StIgnore {
+            root: root.to_path_buf(),
+            patterns: Vec::new(),
+        };
+        ignore.parse_lines(&root.join(STIGNORE_FILE), content, &mut Vec::new())?;
+        Ok(ignore)
+    }
+
+    fn load_file(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ignore file {}", path.display()))?;
+        stack.push(path.to_path_buf());
+        self.parse_lines(path, &content, stack)?;
+        stack.pop();
+        Ok(())
+    }
+
+    /// Parse the content of the ignore file at `path`, includes are relative to its directory
+    fn parse_lines(&mut self, path: &Path, content: &str, stack: &mut Vec<PathBuf>) -> Result<()> {
+        let dir = path.parent().unwrap_or(&self.root).to_path_buf();
+        for (number, line) in content.lines().enumerate() {
+            let invalid = |message: String| Error::Ignore {
+                path: path.to_path_buf(),
+                line: number + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if let Some(include) = line.strip_prefix("#include") {
+                let include = include.trim();
+                if include.is_empty() {
+                    return Err(invalid("missing file name in #include".to_string()));
+                }
+                let included = dir.join(include);
+                if stack.contains(&included) {
+                    return Err(invalid(format!(
+                        "include loop through {}",
+                        included.display()
+                    )));
+                }
+                self.load_file(&included, stack)?;
+                continue;
+            }
+            self.patterns
+                .push(IgnorePattern::parse(line).map_err(invalid)?);
+        }
+        Ok(())
+    }
+
+    fn first_match(&self, path: &Path) -> Option<&IgnorePattern> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if relative.is_empty() {
+            return None;
+        }
+        // like Syncthing, the first matching pattern decides
+        self.patterns.iter().find(|p| p.is_match(&relative))
+    }
+
+    /// Check whether Syncthing ignores the given path
+    ///
+    /// The path may be absolute below the folder root or relative to it.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.first_match(path).is_some_and(|p| !p.negated)
+    }
+
+    /// Check whether the given path is ignored and may be deleted by Syncthing (`(?d)`)
+    pub fn is_deletable(&self, path: &Path) -> bool {
+        self.first_match(path)
+            .is_some_and(|p| !p.negated && p.deletable)
+    }
+
+    /// Returns true if there are no ignore rules
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn ignore(content: &str) -> StIgnore {
+        StIgnore::parse(Path::new("/sync"), content).unwrap()
+    }
+
+    #[test]
+    fn test_unanchored_pattern_matches_any_depth() {
+        let ignore = ignore("target\n*.log");
+        assert!(ignore.is_ignored(Path::new("/sync/target")));
+        assert!(ignore.is_ignored(Path::new("/sync/target/debug/build")));
+        assert!(ignore.is_ignored(Path::new("/sync/project/target/out.o")));
+        assert!(ignore.is_ignored(Path::new("/sync/a/b/c.log")));
+        assert!(!ignore.is_ignored(Path::new("/sync/targets.md")));
+    }
+
+    #[test]
+    fn test_anchored_pattern_matches_root_only() {
+        let ignore = ignore("/cache");
+        assert!(ignore.is_ignored(Path::new("/sync/cache/file.md")));
+        assert!(!ignore.is_ignored(Path::new("/sync/notes/cache/file.md")));
+    }
+
+    #[test]
+    fn test_single_star_does_not_cross_directories() {
+        let ignore = ignore("/notes/*.tmp");
+        assert!(ignore.is_ignored(Path::new("notes/a.tmp")));
+        assert!(!ignore.is_ignored(Path::new("notes/sub/a.tmp")));
+    }
+
+    #[test]
+    fn test_negation_first_match_wins() {
+        let ignore = ignore("!important.log\n*.log");
+        assert!(!ignore.is_ignored(Path::new("/sync/important.log")));
+        assert!(ignore.is_ignored(Path::new("/sync/other.log")));
+    }
+
+    #[test]
+    fn test_case_insensitive_and_deletable() {
+        let ignore = ignore("(?d)(?i).DS_Store\n(?i)!readme.md\n*.md");
+        assert!(ignore.is_ignored(Path::new("/sync/dir/.ds_store")));
+        assert!(ignore.is_deletable(Path::new("/sync/dir/.ds_store")));
+        assert!(!ignore.is_ignored(Path::new("/sync/README.MD")));
+        assert!(ignore.is_ignored(Path::new("/sync/other.md")));
+        assert!(!ignore.is_deletable(Path::new("/sync/other.md")));
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let ignore = ignore("// a comment\n\n   \n");
+        assert!(ignore.is_empty());
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = TempDir::new("stignore");
+        std::fs::write(dir.join(".stignore"), "#include shared-ignore\n/local").unwrap();
+        std::fs::write(dir.join("shared-ignore"), "*.bak").unwrap();
+
+        let ignore = StIgnore::load(&dir).unwrap();
+        assert!(ignore.is_ignored(&dir.join("notes/old.bak")));
+        assert!(ignore.is_ignored(&dir.join("local")));
+        assert!(!ignore.is_ignored(&dir.join("notes/new.md")));
+    }
+
+    #[test]
+    fn test_include_loop() {
+        let dir = TempDir::new("stloop");
+        std::fs::write(dir.join(".stignore"), "#include .stignore").unwrap();
+        assert!(StIgnore::load(&dir).is_err());
+    }
+
+    #[test]
+    fn test_invalid_lines() {
+        let error = |content: &str| match StIgnore::parse(Path::new("/sync"), content) {
+            Err(Error::Ignore { path, line, .. }) => (path, line),
+            other => panic!("expected an ignore error, got {:?}", other.err()),
+        };
+        let path = PathBuf::from("/sync/.stignore");
+        assert_eq!(error("*.log\n!/"), (path.clone(), 2));
+        assert_eq!(error("// comment\n\n#include"), (path.clone(), 3));
+        assert_eq!(error("a[b"), (path, 1));
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Context, Result, bail};
+
+/// Name of the directory Syncthing keeps old versions in by default
+pub const STVERSIONS_DIR: &str = ".stversions";
+
+/// How a folder is shared, the `type` attribute in config.xml
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderType {
+    SendReceive,
+    SendOnly,
+    ReceiveOnly,
+    ReceiveEncrypted,
+}
+
+impl FolderType {
+    fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "" | "sendreceive" | "readwrite" => Self::SendReceive,
+            "sendonly" | "readonly" => Self::SendOnly,
+            "receiveonly" => Self::ReceiveOnly,
+            "receiveencrypted" => Self::ReceiveEncrypted,
+            other => bail!("Unknown folder type '{}'", other),
+        })
+    }
+}
+
+/// Versioning settings of a folder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Versioning {
+    /// Versioning type, e.g. simple, staggered, trashcan or external
+    pub kind: String,
+    /// Custom location of old versions, relative to the folder unless absolute
+    pub path: Option<PathBuf>,
+}
+
+/// A folder shared by the local Syncthing instance
+#[derive(Debug, Clone)]
+pub struct SyncthingFolder {
+    pub id: String,
+    pub label: String,
+    pub path: PathBuf,
+    pub folder_type: FolderType,
+    pub paused: bool,
+    pub versioning: Option<Versioning>,
+}
+
+impl SyncthingFolder {
+    /// Name to show for the folder, the label or the folder ID
+    pub fn name(&self) -> &str {
+        if self.label.is_empty() {
+            &self.id
+        } else {
+            &self.label
+        }
+    }
+
+    /// Receive-only folders must not be changed locally
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self.folder_type,
+            FolderType::ReceiveOnly | FolderType::ReceiveEncrypted
+        )
+    }
+
+    /// Directory holding old versions of files in this folder
+    pub fn versions_dir(&self) -> PathBuf {
+        match self.versioning.as_ref().and_then(|v| v.path.as_ref()) {
+            Some(path) => self.path.join(path),
+            None => self.path.join(STVERSIONS_DIR),
+        }
+    }
+}
+
+/// Settings of the Syncthing GUI and REST API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gui {
+    /// Listen address, e.g. `127.0.0.1:8384`
+    pub address: String,
+    pub api_key: String,
+    pub tls: bool,
+}
+
+impl Gui {
+    /// Base URL of the REST API
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}", scheme, self.address)
+    }
+}
+
+/// Find the config.xml of the local Syncthing instance
+///
+/// Checks `$STCONFDIR` and the state and config directories Syncthing uses by default.
+pub fn find_config() -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(dir) = std::env::var_os("STCONFDIR") {
+        candidates.push(PathBuf::from(dir));
+    }
+    candidates.extend(dirs::state_dir().map(|dir| dir.join("syncthing")));
+    candidates.extend(dirs::config_dir().map(|dir| dir.join("syncthing")));
+    candidates.extend(dirs::home_dir().map(|dir| dir.join(".config/syncthing")));
+    candidates
+        .into_iter()
+        .map(|dir| dir.join("config.xml"))
+        .find(|path| path.exists())
+}
+
+/// Load the folders from a Syncthing config.xml
+pub fn load(path: &Path) -> Result<Vec<SyncthingFolder>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Parse the folders from the content of a Syncthing config.xml
+pub fn parse(content: &str) -> Result<Vec<SyncthingFolder>> {
+    let document = roxmltree::Document::parse(content)?;
+    document
+        .root_element()
+        .children()
+        .filter(|node| node.has_tag_name("folder"))
+        .map(|folder| {
+            let path = folder
+                .attribute("path")
+                .context("Folder without path in Syncthing config")?;
+            Ok(SyncthingFolder {
+                id: folder.attribute("id").unwrap_or_default().to_string(),
+                label: folder.attribute("label").unwrap_or_default().to_string(),
+                path: expand_home(path),
+                folder_type: FolderType::parse(folder.attribute("type").unwrap_or_default())?,
+                paused: child_text(folder, "paused") == Some("true"),
+                versioning: folder
+                    .children()
+                    .find(|node| node.has_tag_name("versioning"))
+                    .and_then(parse_versioning),
+            })
+        })
+        .collect()
+}
+
+/// Load the GUI settings from a Syncthing config.xml
+pub fn load_gui(path: &Path) -> Result<Option<Gui>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_gui(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Parse the GUI settings from the content of a Syncthing config.xml
+pub fn parse_gui(content: &str) -> Result<Option<Gui>> {
+    let document = roxmltree::Document::parse(content)?;
+    let Some(gui) = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("gui"))
+    else {
+        return Ok(None);
+    };
+    let (Some(address), Some(api_key)) = (child_text(gui, "address"), child_text(gui, "apikey"))
+    else {
+        return Ok(None);
+    };
+    Ok(Some(Gui {
+        address: address.to_string(),
+        api_key: api_key.to_string(),
+        tls: gui.attribute("tls") == Some("true"),
+    }))
+}
+
+fn parse_versioning(node: roxmltree::Node) -> Option<Versioning> {
+    let kind = node.attribute("type").filter(|kind| !kind.is_empty())?;
+    // Newer versions use <fsPath>, older ones a versionsPath parameter
+    let path = child_text(node, "fsPath")
+        .or_else(|| {
+            node.children()
+                .find(|param| {
+                    param.has_tag_name("param") && param.attribute("key") == Some("versionsPath")
+                })
+                .and_then(|param| param.attribute("val"))
+        })
+        .filter(|path| !path.is_empty())
+        .map(expand_home);
+    Some(Versioning {
+        kind: kind.to_string(),
+        path,
+    })
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<configuration version="37">
+    <folder id="abcd-1234" label="Wiki" path="/sync/wiki" type="sendreceive">
+        <versioning type="simple">
+            <param key="keep" val="5"></param>
+            <fsPath>/backup/wiki-versions</fsPath>
+        </versioning>
+        <paused>false</paused>
+    </folder>
+    <folder id="efgh-5678" label="" path="/sync/photos" type="receiveonly">
+        <versioning type="staggered">
+            <param key="versionsPath" val="old"></param>
+        </versioning>
+        <paused>false</paused>
+    </folder>
+    <folder id="ijkl-9012" label="Music" path="/sync/music" type="sendonly">
+        <versioning></versioning>
+        <paused>true</paused>
+    </folder>
+    <device id="DEVICE" name="laptop"></device>
+    <gui enabled="true" tls="false" debugging="false">
+        <address>127.0.0.1:8384</address>
+        <apikey>secret</apikey>
+    </gui>
+</configuration>"#;
+
+    #[test]
+    fn test_parse_folders() {
+        let folders = parse(CONFIG).unwrap();
+        assert_eq!(folders.len(), 3);
+
+        assert_eq!(folders[0].name(), "Wiki");
+        assert_eq!(folders[0].folder_type, FolderType::SendReceive);
+        assert!(!folders[0].is_read_only());
+        assert_eq!(
+            folders[0].versions_dir(),
+            PathBuf::from("/backup/wiki-versions")
+        );
+
+        assert_eq!(folders[1].name(), "efgh-5678");
+        assert!(folders[1].is_read_only());
+        assert_eq!(folders[1].versions_dir(), PathBuf::from("/sync/photos/old"));
+
+        assert_eq!(folders[2].folder_type, FolderType::SendOnly);
+        assert!(folders[2].paused);
+        assert_eq!(folders[2].versioning, None);
+        assert_eq!(
+            folders[2].versions_dir(),
+            PathBuf::from("/sync/music/.stversions")
+        );
+    }
+
+    #[test]
+    fn test_parse_gui() {
+        let gui = parse_gui(CONFIG).unwrap().unwrap();
+        assert_eq!(gui.api_key, "secret");
+        assert_eq!(gui.url(), "http://127.0.0.1:8384");
+        assert_eq!(parse_gui("<configuration/>").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_unknown_type() {
+        let config = r#"<configuration><folder id="a" path="/a" type="bogus"/></configuration>"#;
+        assert!(parse(config).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+use crate::selector::Selector;
+
+/// A fresh temporary directory, removed with its content when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create `resolvething-<name>-<pid>` in the temporary directory, replacing leftovers
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("resolvething-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Selector answering with prepared answers in order, recording the items offered
+pub struct ScriptedSelector {
+    answers: RefCell<Vec<Option<usize>>>,
+    inputs: RefCell<Vec<Option<String>>>,
+    pub offered: RefCell<Vec<Vec<String>>>,
+}
+
+impl ScriptedSelector {
+    pub fn new(answers: Vec<Option<usize>>) -> Self {
+        ScriptedSelector {
+            answers: answers.into(),
+            inputs: RefCell::default(),
+            offered: RefCell::default(),
+        }
+    }
+
+    /// Answer text input with these lines in order
+    pub fn with_inputs(self, inputs: Vec<Option<String>>) -> Self {
+        self.inputs.replace(inputs);
+        self
+    }
+}
+
+impl Selector for ScriptedSelector {
+    fn select(&self, _prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        self.offered.borrow_mut().push(items.to_vec());
+        Ok(self.answers.borrow_mut().remove(0))
+    }
+
+    fn input(&self, _prompt: &str) -> Result<Option<String>> {
+        Ok(self.inputs.borrow_mut().remove(0))
+    }
+}
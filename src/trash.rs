@@ -1,21 +1,29 @@
 use crate::config::Config;
-use anyhow::{Context, Result};
+use crate::diff::split_command;
+use crate::{Context, Error, Result};
 
 pub struct Trash;
 
 impl Trash {
+    /// Move a file to the trash with the configured command, e.g. `trash` or `gio trash`
     pub fn trash(file: &str, config: &Config) -> Result<()> {
-        let output = std::process::Command::new(&config.trash_command)
+        let command = split_command(&config.trash_command)?;
+        let Some((program, args)) = command.split_first() else {
+            return Err(Error::InvalidConfigValue {
+                key: "trash_command".to_string(),
+                value: config.trash_command.clone(),
+                expected: "expected a program and its arguments".to_string(),
+            });
+        };
+        let output = std::process::Command::new(program)
+            .args(args)
             .arg(file)
             .output()
-            .context("Failed to execute trash command")?;
+            .map_err(|e| Error::spawn(program, e))?;
 
-        if output.status.success() {
-            println!("Removed: {}", file);
-            Ok(())
-        } else {
-            let error_msg = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Failed to remove {}: {}", file, error_msg)
-        }
+        Error::check_output(&config.trash_command, output)
+            .with_context(|| format!("Failed to remove {}", file))?;
+        eprintln!("Removed: {}", file);
+        Ok(())
     }
 }
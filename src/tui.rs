@@ -0,0 +1,522 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, List, ListState, Paragraph, Row, Table, TableState},
+};
+use syntect::{easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet};
+
+use crate::diff::{DiffLine, diff_files};
+use crate::duplicates::SyncThingFile;
+use crate::{Context, Error, Result};
+
+/// Maximum number of lines shown in the preview pane
+const PREVIEW_LINES: usize = 500;
+
+/// Theme used for syntax highlighting in the preview pane
+const PREVIEW_THEME: &str = "base16-ocean.dark";
+
+/// Editor used to open files if `$EDITOR` is not set
+const DEFAULT_EDITOR: &str = "nvim";
+
+/// Action chosen by the user for a duplicate group or conflict
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Keep the file at the given index, remove the others
+    Keep(usize),
+    /// Keep the files at the given indices, remove the others
+    KeepSeveral(Vec<usize>),
+    /// Keep all files and never offer this item again
+    KeepAll,
+    /// Keep the file at the given index under a new name, remove the others
+    Rename(usize, String),
+    /// Merge the file at the given index with the others
+    Merge(usize),
+    /// Replace the files with an old version from the versions directory
+    History,
+    /// Leave the item untouched
+    Skip,
+    /// Stop processing any further items
+    Quit,
+}
+
+/// Position of the current item among all items of a run
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub current: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn new(current: usize, total: usize) -> Self {
+        Progress { current, total }
+    }
+}
+
+/// Metadata of a file shown in the member list
+struct FileInfo {
+    path: String,
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+    device: Option<String>,
+    file_type: &'static str,
+}
+
+impl FileInfo {
+    fn new(path: &str) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        let file = SyncThingFile::new(path.to_string());
+        FileInfo {
+            path: path.to_string(),
+            size: metadata.as_ref().map(|m| m.len()),
+            modified: metadata.and_then(|m| m.modified().ok()),
+            device: file.device(),
+            file_type: file.file_type.label(),
+        }
+    }
+
+    fn row(&self, marked: bool) -> Row<'static> {
+        let size = self
+            .size
+            .map(format_size)
+            .unwrap_or_else(|| "-".to_string());
+        let modified = self
+            .modified
+            .map(|time| {
+                DateTime::<Local>::from(time)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "-".to_string());
+        Row::new(vec![
+            Cell::from(if marked { "*" } else { " " }),
+            Cell::from(self.path.clone()),
+            Cell::from(size),
+            Cell::from(modified),
+            Cell::from(self.device.clone().unwrap_or_else(|| "-".to_string())),
+            Cell::from(self.file_type),
+        ])
+    }
+}
+
+/// Format a byte count for humans
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Native terminal UI to pick an action for a group of files
+pub struct Tui {
+    title: String,
+    files: Vec<FileInfo>,
+    progress: Progress,
+    allow_merge: bool,
+    state: TableState,
+    /// Files marked to be kept together
+    marked: BTreeSet<usize>,
+    /// File the selected file is diffed against in the preview pane
+    diff_with: Option<usize>,
+    /// New name typed for the selected file, while renaming
+    input: Option<String>,
+    preview: Vec<Line<'static>>,
+    message: Option<String>,
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+}
+
+impl Tui {
+    /// Show the files and let the user choose what to do with them
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - Kind of item shown, e.g. "Duplicates"
+    /// * `files` - Paths of the files belonging to the item
+    /// * `progress` - Position of the item among all items of the run
+    /// * `allow_merge` - Whether the merge keybinding is offered
+    pub fn choose(
+        title: &str,
+        files: &[String],
+        progress: Progress,
+        allow_merge: bool,
+    ) -> Result<Action> {
+        if files.is_empty() {
+            return Ok(Action::Skip);
+        }
+        let mut tui = Tui {
+            title: title.to_string(),
+            files: files.iter().map(|f| FileInfo::new(f)).collect(),
+            progress,
+            allow_merge,
+            state: TableState::default().with_selected(0),
+            marked: BTreeSet::new(),
+            diff_with: None,
+            input: None,
+            preview: Vec::new(),
+            message: None,
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            themes: ThemeSet::load_defaults(),
+        };
+        tui.update_preview();
+
+        let mut terminal = ratatui::try_init().context("Failed to initialize terminal")?;
+        let result = tui.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    /// Show a plain list of entries and let the user pick one, `None` if cancelled
+    ///
+    /// Unlike [`Tui::choose`] the entries are not treated as files.
+    pub fn pick(prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        Menu::select(prompt, items)
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<Action> {
+        loop {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .context("Failed to draw terminal UI")?;
+
+            let Event::Key(key) = event::read().context("Failed to read terminal event")? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let selected = self.selected();
+            if let Some(input) = &mut self.input {
+                match key.code {
+                    KeyCode::Char(c) => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Enter if !input.trim().is_empty() => {
+                        return Ok(Action::Rename(selected, input.trim().to_string()));
+                    }
+                    KeyCode::Esc => self.input = None,
+                    _ => {}
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Down | KeyCode::Char('j') => self.select(selected + 1),
+                KeyCode::Up | KeyCode::Char('k') => self.select(selected.saturating_sub(1)),
+                KeyCode::Char(' ') => {
+                    if !self.marked.remove(&selected) {
+                        self.marked.insert(selected);
+                    }
+                    self.select(selected + 1);
+                }
+                KeyCode::Enter => {
+                    return Ok(match self.marked.len() {
+                        0 => Action::Keep(selected),
+                        1 => Action::Keep(*self.marked.first().unwrap_or(&selected)),
+                        _ => Action::KeepSeveral(self.marked.iter().copied().collect()),
+                    });
+                }
+                KeyCode::Char('a') => return Ok(Action::KeepAll),
+                KeyCode::Char('r') => self.input = Some(String::new()),
+                KeyCode::Char('d') if self.files.len() > 1 => {
+                    self.diff_with = match self.diff_with {
+                        Some(_) => None,
+                        None => Some(self.diff_partner(selected)),
+                    };
+                    self.update_preview();
+                }
+                KeyCode::Char('s') | KeyCode::Esc => return Ok(Action::Skip),
+                KeyCode::Char('q') => return Ok(Action::Quit),
+                KeyCode::Char('m') if self.allow_merge => return Ok(Action::Merge(selected)),
+                KeyCode::Char('h') if self.allow_merge => return Ok(Action::History),
+                KeyCode::Char('o') => {
+                    ratatui::restore();
+                    let result = open_in_editor(&self.files[selected].path);
+                    *terminal = ratatui::try_init().context("Failed to initialize terminal")?;
+                    self.message = result.err().map(|e| e.to_string());
+                    self.files[selected] = FileInfo::new(&self.files[selected].path);
+                    self.update_preview();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// File to diff the selected file against: the only other marked file, or the next one
+    fn diff_partner(&self, selected: usize) -> usize {
+        let others: Vec<usize> = self
+            .marked
+            .iter()
+            .copied()
+            .filter(|&index| index != selected)
+            .collect();
+        match others.as_slice() {
+            [other] => *other,
+            _ => (selected + 1) % self.files.len(),
+        }
+    }
+
+    fn selected(&self) -> usize {
+        self.state.selected().unwrap_or(0)
+    }
+
+    fn select(&mut self, index: usize) {
+        let index = index.min(self.files.len() - 1);
+        if index != self.selected() {
+            self.state.select(Some(index));
+            if self.diff_with.is_some() {
+                self.diff_with = Some(self.diff_partner(index));
+            }
+            self.update_preview();
+        }
+    }
+
+    fn update_preview(&mut self) {
+        let path = &self.files[self.selected()].path;
+        if let Some(other) = self.diff_with {
+            let other = &self.files[other].path;
+            self.preview = match diff_files(path, other) {
+                Ok(diff) => diff.iter().take(PREVIEW_LINES).map(diff_line).collect(),
+                Err(e) => vec![Line::from(e.to_string())],
+            };
+            return;
+        }
+        self.preview = match std::fs::read(path) {
+            Ok(content) if content.contains(&0) => vec![Line::from("binary file")],
+            Ok(content) => self.highlight(path, &String::from_utf8_lossy(&content)),
+            Err(e) => vec![Line::from(format!("cannot read file: {}", e))],
+        };
+    }
+
+    fn highlight(&self, path: &str, content: &str) -> Vec<Line<'static>> {
+        // conflict copies carry their real extension at the end as well
+        let syntax = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntaxes.find_syntax_by_extension(ext))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, &self.themes.themes[PREVIEW_THEME]);
+
+        syntect::util::LinesWithEndings::from(content)
+            .take(PREVIEW_LINES)
+            .map(|line| {
+                let spans = highlighter
+                    .highlight_line(line, &self.syntaxes)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let fg = style.foreground;
+                        Span::styled(
+                            text.trim_end_matches(['\n', '\r']).to_string(),
+                            Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b)),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Line::from(spans)
+            })
+            .collect()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let direction = if main.width < 120 {
+            Direction::Vertical
+        } else {
+            Direction::Horizontal
+        };
+        let panes = Layout::default()
+            .direction(direction)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(main);
+
+        let rows = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| file.row(self.marked.contains(&index)));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(1),
+                Constraint::Fill(1),
+                Constraint::Length(10),
+                Constraint::Length(16),
+                Constraint::Length(8),
+                Constraint::Length(8),
+            ],
+        )
+        .header(
+            Row::new(["", "Path", "Size", "Modified", "Device", "Type"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(self.title.as_str()),
+        );
+        frame.render_stateful_widget(table, panes[0], &mut self.state);
+
+        let preview_title = match self.diff_with {
+            Some(other) => format!("Diff against {}", self.files[other].path),
+            None => "Preview".to_string(),
+        };
+        let preview = Paragraph::new(self.preview.clone())
+            .block(Block::default().borders(Borders::ALL).title(preview_title));
+        frame.render_widget(preview, panes[1]);
+
+        if let Some(input) = &self.input {
+            let text = format!(" rename to: {}▏ (enter confirm · esc cancel)", input);
+            frame.render_widget(
+                Paragraph::new(text).style(Style::default().add_modifier(Modifier::REVERSED)),
+                status,
+            );
+            return;
+        }
+
+        let mut keys =
+            "enter keep · space mark · a keep all · r rename · o open · d diff".to_string();
+        if self.allow_merge {
+            keys.push_str(" · m merge · h history");
+        }
+        keys.push_str(" · s skip · q quit");
+        let mut text = format!(
+            " {}/{} · {}",
+            self.progress.current, self.progress.total, keys
+        );
+        if let Some(message) = &self.message {
+            text = format!("{} · {}", text, message);
+        }
+        frame.render_widget(
+            Paragraph::new(text).style(Style::default().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+    }
+}
+
+/// Plain list of entries for picking one, without file columns, preview or file actions
+struct Menu {
+    prompt: String,
+    items: Vec<String>,
+    state: ListState,
+}
+
+impl Menu {
+    /// Show the entries and let the user pick one, `None` if cancelled
+    fn select(prompt: &str, items: &[String]) -> Result<Option<usize>> {
+        if items.is_empty() {
+            return Ok(None);
+        }
+        let mut menu = Menu {
+            prompt: prompt.to_string(),
+            items: items.to_vec(),
+            state: ListState::default().with_selected(Some(0)),
+        };
+        let mut terminal = ratatui::try_init().context("Failed to initialize terminal")?;
+        let result = menu.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<Option<usize>> {
+        loop {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .context("Failed to draw terminal UI")?;
+
+            let Event::Key(key) = event::read().context("Failed to read terminal event")? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let selected = self.state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Down | KeyCode::Char('j') => self
+                    .state
+                    .select(Some((selected + 1).min(self.items.len() - 1))),
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.state.select(Some(selected.saturating_sub(1)))
+                }
+                KeyCode::Enter => return Ok(Some(selected)),
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let list = List::new(self.items.clone())
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.prompt.as_str()),
+            );
+        frame.render_stateful_widget(list, main, &mut self.state);
+        frame.render_widget(
+            Paragraph::new(" enter select · esc cancel")
+                .style(Style::default().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+    }
+}
+
+/// Render a diff line in the usual colors
+fn diff_line(line: &DiffLine) -> Line<'static> {
+    let color = match line {
+        DiffLine::Same(_) => Color::Reset,
+        DiffLine::Removed(_) => Color::Red,
+        DiffLine::Added(_) => Color::Green,
+    };
+    Line::styled(line.to_string(), Style::default().fg(color))
+}
+
+/// Open a file in `$EDITOR` and wait for the editor to exit
+pub fn open_in_editor(file: &str) -> Result<()> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let status = Command::new(&editor)
+        .arg(file)
+        .status()
+        .map_err(|e| Error::spawn(&editor, e))?;
+    if !status.success() {
+        return Err(Error::ToolFailed {
+            tool: editor,
+            status,
+            stderr: String::new(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
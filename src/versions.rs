@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use walkdir::WalkDir;
+
+use crate::config::{Config, Profile};
+use crate::diff::diff_files;
+use crate::list::{OutputFormat, write_entries};
+use crate::selector::Selector;
+use crate::trash::Trash;
+use crate::tui::format_size;
+use crate::{Context, Error, Result};
+
+/// Format of the timestamp Syncthing appends to old versions
+const VERSION_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Answers offered for a selected version
+const PREVIEW_ENTRY: &str = "preview";
+const DIFF_ENTRY: &str = "diff against current file";
+const RESTORE_ENTRY: &str = "restore";
+const BACK_ENTRY: &str = "back";
+
+/// An old version of a file kept by Syncthing's file versioning
+#[derive(Debug, Clone, Serialize)]
+pub struct Version {
+    pub path: PathBuf,
+    /// When the version was archived, in local time
+    #[serde(serialize_with = "serialize_time")]
+    pub timestamp: NaiveDateTime,
+    pub size: u64,
+}
+
+impl Version {
+    /// Short description for selectors, e.g. `2024-01-01 12:00:00  1.2 KiB`
+    pub fn label(&self) -> String {
+        format!("{}  {}", self.timestamp, format_size(self.size))
+    }
+}
+
+fn serialize_time<S: Serializer>(time: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.format("%Y-%m-%dT%H:%M:%S").to_string())
+}
+
+/// Split the name of an old version into the name of the file and the time it was archived
+///
+/// Syncthing inserts `~YYYYMMDD-HHMMSS` before the extension, e.g. `notes~20240101-120000.md`.
+pub fn untag(name: &str) -> Option<(String, NaiveDateTime)> {
+    let regex = Regex::new(r"^(.*)~(\d{8}-\d{6})(\.[^.]*)?$").expect("valid regex");
+    let captures = regex.captures(name)?;
+    let time = NaiveDateTime::parse_from_str(&captures[2], VERSION_TIME_FORMAT).ok()?;
+    let extension = captures.get(3).map_or("", |m| m.as_str());
+    Some((format!("{}{}", &captures[1], extension), time))
+}
+
+/// The profile of the folder containing `file`, the innermost if folders are nested
+pub fn profile_of<'a>(profiles: &'a [Profile], file: &Path) -> Option<&'a Profile> {
+    profiles
+        .iter()
+        .filter(|profile| file.starts_with(&profile.config.working_directory))
+        .max_by_key(|profile| profile.config.working_directory.as_os_str().len())
+}
+
+/// All old versions of `file` in the versions directory of its folder, newest first
+pub fn find_versions(profile: &Profile, file: &Path) -> Result<Vec<Version>> {
+    let relative = file
+        .strip_prefix(&profile.config.working_directory)
+        .ok()
+        .with_context(|| format!("{} is not in {}", file.display(), profile.name))?;
+    let name = relative
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Invalid file name {}", file.display()))?;
+    let directory = match relative.parent() {
+        Some(parent) => profile.versions_dir.join(parent),
+        None => profile.versions_dir.clone(),
+    };
+    let Ok(entries) = std::fs::read_dir(&directory) else {
+        return Ok(Vec::new());
+    };
+    let mut versions = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some((original, timestamp)) = entry.file_name().to_str().and_then(untag) else {
+            continue;
+        };
+        if original == name && entry.file_type()?.is_file() {
+            versions.push(Version {
+                path: entry.path(),
+                timestamp,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    versions.sort_by_key(|version| std::cmp::Reverse(version.timestamp));
+    Ok(versions)
+}
+
+/// All old versions in the versions directory of a folder, by the file they belong to
+///
+/// The versions of each file are sorted newest first.
+pub fn all_versions(profile: &Profile) -> Result<BTreeMap<PathBuf, Vec<Version>>> {
+    let mut files: BTreeMap<PathBuf, Vec<Version>> = BTreeMap::new();
+    for entry in WalkDir::new(&profile.versions_dir) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // a folder without old versions
+            Err(e) if e.depth() == 0 => return Ok(files),
+            Err(e) => return Err(e).context("Failed to read old versions"),
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Some((original, timestamp)) = entry.file_name().to_str().and_then(untag) else {
+            continue;
+        };
+        let relative = entry
+            .path()
+            .parent()
+            .and_then(|parent| parent.strip_prefix(&profile.versions_dir).ok())
+            .unwrap_or(Path::new(""));
+        let file = profile
+            .config
+            .working_directory
+            .join(relative)
+            .join(original);
+        files.entry(file).or_default().push(Version {
+            path: entry.path().to_path_buf(),
+            timestamp,
+            size: entry.metadata()?.len(),
+        });
+    }
+    for versions in files.values_mut() {
+        versions.sort_by_key(|version| std::cmp::Reverse(version.timestamp));
+    }
+    Ok(files)
+}
+
+/// Replace `file` with an old version, moving the current file to the trash first
+pub fn restore(version: &Version, file: &Path, config: &Config) -> Result<()> {
+    if file.exists() {
+        Trash::trash(&file.to_string_lossy(), config)?;
+    } else if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::copy(&version.path, file).with_context(|| {
+        format!(
+            "Failed to restore {} to {}",
+            version.path.display(),
+            file.display()
+        )
+    })?;
+    eprintln!("restored {} from {}", file.display(), version.timestamp);
+    Ok(())
+}
+
+/// Let the user pick one of the versions, `None` if aborted
+pub fn select_version<'a>(
+    selector: &dyn Selector,
+    versions: &'a [Version],
+) -> Result<Option<&'a Version>> {
+    let items: Vec<String> = versions.iter().map(Version::label).collect();
+    Ok(selector
+        .select("version", &items)?
+        .map(|index| &versions[index]))
+}
+
+/// Print the old versions of a file
+pub fn list_versions(
+    versions: &[Version],
+    format: OutputFormat,
+    out: &mut impl std::io::Write,
+) -> Result<()> {
+    write_entries(
+        out,
+        format,
+        versions.iter().collect(),
+        &["ARCHIVED", "SIZE", "PATH"],
+        |version| {
+            vec![
+                version.timestamp.to_string(),
+                format_size(version.size),
+                version.path.display().to_string(),
+            ]
+        },
+    )
+}
+
+/// Browse the old versions of a file, previewing, diffing and restoring them
+///
+/// Versions are never restored into read-only folders.
+pub fn browse(selector: &dyn Selector, profile: &Profile, file: &Path) -> Result<()> {
+    let versions = find_versions(profile, file)?;
+    if versions.is_empty() {
+        return Err(Error::NoVersions {
+            path: file.to_path_buf(),
+        });
+    }
+    let mut actions = vec![PREVIEW_ENTRY, DIFF_ENTRY, RESTORE_ENTRY, BACK_ENTRY];
+    if profile.read_only {
+        eprintln!(
+            "{} is read-only, versions can not be restored",
+            profile.name
+        );
+        actions.retain(|action| *action != RESTORE_ENTRY);
+    }
+    let actions: Vec<String> = actions.into_iter().map(String::from).collect();
+    while let Some(version) = select_version(selector, &versions)? {
+        loop {
+            let prompt = version.label();
+            match selector
+                .select(&prompt, &actions)?
+                .map(|i| actions[i].as_str())
+            {
+                Some(PREVIEW_ENTRY) => {
+                    let content = std::fs::read(&version.path)
+                        .with_context(|| format!("Failed to read {}", version.path.display()))?;
+                    eprintln!("{}", String::from_utf8_lossy(&content));
+                }
+                Some(DIFF_ENTRY) => {
+                    let diff =
+                        diff_files(&version.path.to_string_lossy(), &file.to_string_lossy())?;
+                    for line in diff {
+                        eprintln!("{}", line);
+                    }
+                }
+                Some(RESTORE_ENTRY) => {
+                    restore(version, file, &profile.config)?;
+                    return Ok(());
+                }
+                _ => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ScriptedSelector, TempDir};
+
+    #[test]
+    fn test_untag() {
+        let time = |s: &str| NaiveDateTime::parse_from_str(s, VERSION_TIME_FORMAT).unwrap();
+        assert_eq!(
+            untag("notes~20240101-120000.md"),
+            Some(("notes.md".to_string(), time("20240101-120000")))
+        );
+        assert_eq!(
+            untag("archive.tar~20231231-235959.gz"),
+            Some(("archive.tar.gz".to_string(), time("20231231-235959")))
+        );
+        assert_eq!(
+            untag("Makefile~20240101-120000"),
+            Some(("Makefile".to_string(), time("20240101-120000")))
+        );
+        assert_eq!(untag("notes.md"), None);
+        assert_eq!(untag("notes~20241301-120000.md"), None);
+    }
+
+    #[test]
+    fn test_find_versions() {
+        let dir = TempDir::new("versions");
+        let profile = Profile::for_test(&dir);
+        let versions_dir = profile.versions_dir.join("sub");
+        std::fs::create_dir_all(&versions_dir).unwrap();
+        std::fs::write(versions_dir.join("a~20240101-120000.md"), "old").unwrap();
+        std::fs::write(versions_dir.join("a~20240301-120000.md"), "newer").unwrap();
+        std::fs::write(versions_dir.join("b~20240301-120000.md"), "other").unwrap();
+
+        let versions = find_versions(&profile, &dir.join("sub/a.md")).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].size, 5);
+        assert!(versions[0].timestamp > versions[1].timestamp);
+        assert!(
+            find_versions(&profile, &dir.join("c.md"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_browse_read_only() {
+        let dir = TempDir::new("versions-read-only");
+        let profile = Profile {
+            read_only: true,
+            ..Profile::for_test(&dir)
+        };
+        std::fs::create_dir_all(&profile.versions_dir).unwrap();
+        std::fs::write(profile.versions_dir.join("a~20240101-120000.md"), "old").unwrap();
+        std::fs::write(dir.join("a.md"), "current").unwrap();
+        // pick the version, go back, then leave
+        let selector = ScriptedSelector::new(vec![Some(0), None, None]);
+
+        browse(&selector, &profile, &dir.join("a.md")).unwrap();
+        let offered = selector.offered.into_inner();
+        assert_eq!(offered[1], [PREVIEW_ENTRY, DIFF_ENTRY, BACK_ENTRY]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.md")).unwrap(),
+            "current"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::app::App;
+use crate::artifacts::ArtifactKind;
+use crate::batch::Scan;
+use crate::config::Profile;
+use crate::sync_conflict_regex;
+use crate::{Context, Result};
+
+/// Watch the folders of the app and resolve new conflicts and artifacts as they appear
+///
+/// Events are collected until the folders were quiet for `debounce`. Items the
+/// strategies do not resolve are queued for the next interactive session.
+pub fn watch(app: &mut App, debounce: Duration) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("Failed to start watching")?;
+    for profile in app.profiles() {
+        let directory = &profile.config.working_directory;
+        watcher
+            .watch(directory, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", directory.display()))?;
+        eprintln!("watching {}", directory.display());
+    }
+
+    // Catch up on everything that appeared while nobody was watching
+    let mut scans: BTreeMap<usize, Scan> = (0..app.profiles().len())
+        .map(|index| (index, Scan::All))
+        .collect();
+    loop {
+        for (&index, &scan) in &scans {
+            // a folder failing must not stop watching the others
+            if let Err(e) = app.run_folder(index, scan) {
+                eprintln!("Error in {}: {:#}", app.profiles()[index].name, e);
+            }
+        }
+        let (summary, queued) = app.defer_pending()?;
+        if !summary.resolved.is_empty() || queued > 0 || !summary.failed.is_empty() {
+            eprintln!(
+                "{} resolved, {} queued, {} failed",
+                summary.resolved.len(),
+                queued,
+                summary.failed.len()
+            );
+        }
+
+        scans.clear();
+        let event = receiver.recv().ok().context("File watcher stopped")?;
+        add_scans(&mut scans, event, app.profiles());
+        while let Ok(event) = receiver.recv_timeout(debounce) {
+            add_scans(&mut scans, event, app.profiles());
+        }
+    }
+}
+
+/// Add the scans an event calls for
+fn add_scans(
+    scans: &mut BTreeMap<usize, Scan>,
+    event: notify::Result<Event>,
+    profiles: &[Profile],
+) {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            eprintln!("warning: {}", e);
+            return;
+        }
+    };
+    // Files appear either directly or when Syncthing renames its temporary file,
+    // files renamed away, e.g. into the trash, need no attention
+    let paths = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            &event.paths[..]
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => &event.paths[1..],
+        _ => return,
+    };
+    for path in paths {
+        if let Some((index, scan)) = scan_for(path, profiles) {
+            let merged = match scans.get(&index) {
+                Some(&existing) if existing != scan => Scan::All,
+                _ => scan,
+            };
+            scans.insert(index, merged);
+        }
+    }
+}
+
+/// Folder and scan a new file calls for, `None` if it needs no attention
+fn scan_for(path: &Path, profiles: &[Profile]) -> Option<(usize, Scan)> {
+    let name = path.file_name()?.to_str()?;
+    let scan = if sync_conflict_regex().is_match(name) {
+        Scan::Conflicts
+    } else {
+        // Syncthing's own temporary files go away once a sync completes
+        match ArtifactKind::of(name)? {
+            ArtifactKind::SyncthingTemp | ArtifactKind::VimSwap | ArtifactKind::Backup => {
+                return None;
+            }
+            ArtifactKind::Orig | ArtifactKind::Tmp => Scan::Dupes,
+        }
+    };
+    let (index, _) = profiles
+        .iter()
+        .enumerate()
+        .filter(|(_, profile)| {
+            path.starts_with(&profile.config.working_directory)
+                && !path.starts_with(&profile.versions_dir)
+        })
+        .max_by_key(|(_, profile)| profile.config.working_directory.as_os_str().len())?;
+    Some((index, scan))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_for() {
+        let profiles = [
+            Profile::for_test(Path::new("/sync")),
+            Profile::for_test(Path::new("/sync/wiki")),
+        ];
+        let scan = |path: &str| scan_for(Path::new(path), &profiles);
+        assert_eq!(
+            scan("/sync/wiki/a.sync-conflict-20240101-120000-ABCDEFG.md"),
+            Some((1, Scan::Conflicts))
+        );
+        assert_eq!(scan("/sync/notes.md.orig"), Some((0, Scan::Dupes)));
+        assert_eq!(scan("/sync/wiki/.syncthing.a.md.tmp"), None);
+        assert_eq!(scan("/sync/wiki/a.md"), None);
+        assert_eq!(scan("/sync/.stversions/a.md.orig"), None);
+        assert_eq!(scan("/elsewhere/a.md.orig"), None);
+    }
+}